@@ -0,0 +1,244 @@
+use crate::model::{Color, Rank, Square};
+use std::iter;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+
+/// A set of squares, stored as a 64-bit integer.
+///
+/// The square on file `f` and rank `r` (both starting from 0) corresponds to bit `8 * r + f` (see
+/// [`Square::index`]).
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Bitboard(pub u64);
+
+impl Bitboard {
+    pub const EMPTY: Self = Self(0);
+
+    pub fn from_square(square: Square) -> Self {
+        Self(1 << square.index())
+    }
+
+    /// Returns the set of all the squares of a rank.
+    pub fn rank(rank: Rank) -> Self {
+        Self(0xff << (8 * rank.index()))
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, square: Square) -> bool {
+        self.0 & (1 << square.index()) != 0
+    }
+
+    pub fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// Returns the square with the lowest index in this set, if it is not empty.
+    pub fn first(self) -> Option<Square> {
+        Square::from_index(self.0.trailing_zeros() as usize)
+    }
+
+    /// Returns an iterator over the squares of this set, by increasing index.
+    pub fn squares(self) -> impl Iterator<Item = Square> {
+        let mut bits = self.0;
+        iter::from_fn(move || {
+            let square = Self(bits).first()?;
+            bits &= bits - 1;
+            Some(square)
+        })
+    }
+
+    /// Mirrors this set vertically (i.e., along a horizontal axis).
+    pub fn mirror(self) -> Self {
+        Self(self.0.swap_bytes())
+    }
+}
+
+impl BitAnd for Bitboard {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl BitAndAssign for Bitboard {
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.0 &= rhs.0
+    }
+}
+
+impl BitOr for Bitboard {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Bitboard {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0
+    }
+}
+
+impl BitXor for Bitboard {
+    type Output = Self;
+
+    fn bitxor(self, rhs: Self) -> Self {
+        Self(self.0 ^ rhs.0)
+    }
+}
+
+impl BitXorAssign for Bitboard {
+    fn bitxor_assign(&mut self, rhs: Self) {
+        self.0 ^= rhs.0
+    }
+}
+
+impl Not for Bitboard {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(!self.0)
+    }
+}
+
+/// Returns, for each square, the set of squares that can be reached from it by one of some
+/// `(file, rank)` offsets.
+const fn leaper_attacks(offsets: &[(i32, i32)]) -> [u64; 64] {
+    let mut attacks = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let mut i = 0;
+        while i < offsets.len() {
+            let file = (square % 8) as i32 + offsets[i].0;
+            let rank = (square / 8) as i32 + offsets[i].1;
+            if 0 <= file && file < 8 && 0 <= rank && rank < 8 {
+                attacks[square] |= 1 << (8 * rank + file);
+            }
+            i += 1;
+        }
+        square += 1;
+    }
+    attacks
+}
+
+static KNIGHT_ATTACKS: [u64; 64] = leaper_attacks(&[
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+]);
+
+static KING_ATTACKS: [u64; 64] = leaper_attacks(&[
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+]);
+
+static WHITE_PAWN_ATTACKS: [u64; 64] = leaper_attacks(&[(-1, 1), (1, 1)]);
+
+static BLACK_PAWN_ATTACKS: [u64; 64] = leaper_attacks(&[(-1, -1), (1, -1)]);
+
+/// The directions sliding pieces move in, as `(file, rank)` steps.
+///
+/// The first four directions go towards higher square indices, and the last four towards lower
+/// ones.
+const DIRECTIONS: [(i32, i32); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (-1, 1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (1, -1),
+];
+
+/// Returns, for each direction and each square, the set of squares a sliding piece on that square
+/// could reach in that direction on an empty board.
+const fn rays() -> [[u64; 64]; 8] {
+    let mut rays = [[0; 64]; 8];
+    let mut direction = 0;
+    while direction < 8 {
+        let (file_step, rank_step) = DIRECTIONS[direction];
+        let mut square = 0;
+        while square < 64 {
+            let mut file = (square % 8) as i32 + file_step;
+            let mut rank = (square / 8) as i32 + rank_step;
+            while 0 <= file && file < 8 && 0 <= rank && rank < 8 {
+                rays[direction][square] |= 1 << (8 * rank + file);
+                file += file_step;
+                rank += rank_step;
+            }
+            square += 1;
+        }
+        direction += 1;
+    }
+    rays
+}
+
+static RAYS: [[u64; 64]; 8] = rays();
+
+/// Returns the squares a sliding piece on a square attacks in a direction (see [`DIRECTIONS`]),
+/// up to and including the first occupied square.
+fn ray_attacks(direction: usize, square: Square, occupied: Bitboard) -> Bitboard {
+    let ray = RAYS[direction][square.index()];
+    let blockers = ray & occupied.0;
+    if blockers == 0 {
+        return Bitboard(ray);
+    }
+    let blocker = if direction < 4 {
+        blockers.trailing_zeros()
+    } else {
+        63 - blockers.leading_zeros()
+    };
+    Bitboard(ray ^ RAYS[direction][blocker as usize])
+}
+
+pub fn knight_attacks(square: Square) -> Bitboard {
+    Bitboard(KNIGHT_ATTACKS[square.index()])
+}
+
+pub fn king_attacks(square: Square) -> Bitboard {
+    Bitboard(KING_ATTACKS[square.index()])
+}
+
+/// Returns the squares a pawn of some color attacks from a square.
+pub fn pawn_attacks(color: Color, square: Square) -> Bitboard {
+    match color {
+        Color::White => Bitboard(WHITE_PAWN_ATTACKS[square.index()]),
+        Color::Black => Bitboard(BLACK_PAWN_ATTACKS[square.index()]),
+    }
+}
+
+/// Returns the squares a bishop attacks from a square, given the occupied squares.
+pub fn bishop_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    [1, 3, 5, 7]
+        .into_iter()
+        .map(|direction| ray_attacks(direction, square, occupied))
+        .fold(Bitboard::EMPTY, |attacks, ray| attacks | ray)
+}
+
+/// Returns the squares a rook attacks from a square, given the occupied squares.
+pub fn rook_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    [0, 2, 4, 6]
+        .into_iter()
+        .map(|direction| ray_attacks(direction, square, occupied))
+        .fold(Bitboard::EMPTY, |attacks, ray| attacks | ray)
+}
+
+/// Returns the squares a queen attacks from a square, given the occupied squares.
+pub fn queen_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    bishop_attacks(square, occupied) | rook_attacks(square, occupied)
+}
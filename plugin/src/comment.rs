@@ -0,0 +1,321 @@
+use crate::error::Error;
+use crate::json::Json;
+use crate::model::Square;
+use crate::utils::{CharExt, FromChar, Name};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// A color used to highlight squares and draw arrows in comments.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Highlight {
+    Green,
+    Red,
+    Yellow,
+    Blue,
+}
+
+impl FromChar for Highlight {
+    type Err = Error;
+
+    fn from_char(c: char) -> crate::Result<Self> {
+        Ok(match c {
+            'G' => Self::Green,
+            'R' => Self::Red,
+            'Y' => Self::Yellow,
+            'B' => Self::Blue,
+            _ => Err(format!("invalid PGN: invalid highlight color: {c:?}"))?,
+        })
+    }
+}
+
+impl Name for Highlight {
+    fn name(&self) -> String {
+        match self {
+            Self::Green => "G".into(),
+            Self::Red => "R".into(),
+            Self::Yellow => "Y".into(),
+            Self::Blue => "B".into(),
+        }
+    }
+}
+
+/// A square highlighted in a comment.
+#[derive(Debug, Copy, Clone)]
+pub struct MarkedSquare {
+    pub color: Highlight,
+    pub square: Square,
+}
+
+impl MarkedSquare {
+    pub fn to_json(self) -> Json {
+        Json::object([
+            ("square", self.square.to_string().into()),
+            ("color", self.color.name().into()),
+        ])
+    }
+}
+
+/// An arrow drawn in a comment.
+#[derive(Debug, Copy, Clone)]
+pub struct Arrow {
+    pub color: Highlight,
+    pub from: Square,
+    pub to: Square,
+}
+
+impl Arrow {
+    pub fn to_json(self) -> Json {
+        Json::object([
+            ("from", self.from.to_string().into()),
+            ("to", self.to.to_string().into()),
+            ("color", self.color.name().into()),
+        ])
+    }
+}
+
+/// An engine evaluation, from White's point of view.
+#[derive(Debug, Copy, Clone)]
+pub enum Evaluation {
+    /// An advantage, in pawns.
+    Pawns(f64),
+    /// A forced mate in a number of moves. The number is negative if Black mates.
+    Mate(i32),
+}
+
+impl FromStr for Evaluation {
+    type Err = Error;
+
+    /// Parses an evaluation, such as `+0.34`, `-1.2` or `#-3`. A search depth following a comma
+    /// (e.g., `0.34,20`) is ignored.
+    fn from_str(s: &str) -> crate::Result<Self> {
+        let invalid = || format!("invalid PGN: invalid evaluation: {s:?}");
+        let value = s.split(',').next().unwrap_or(s).trim();
+        match value.strip_prefix('#') {
+            Some(mate) => Ok(Self::Mate(mate.parse().map_err(|_| invalid())?)),
+            None => {
+                let pawns = value.parse::<f64>().map_err(|_| invalid())?;
+                pawns
+                    .is_finite()
+                    .then_some(Self::Pawns(pawns))
+                    .ok_or_else(|| invalid().into())
+            }
+        }
+    }
+}
+
+/// Parses a duration written `h:mm:ss`, and returns it in seconds.
+///
+/// Hours can be omitted, and seconds can have a fractional part (e.g., `0:00:05.3`).
+fn parse_duration(s: &str) -> crate::Result<f64> {
+    let invalid = || format!("invalid PGN: invalid duration: {s:?}");
+    let parts = s.trim().split(':').collect::<Vec<_>>();
+    let (seconds, minutes_and_hours) = parts.split_last().ok_or_else(invalid)?;
+    if minutes_and_hours.len() > 2 {
+        Err(invalid())?
+    }
+    let seconds = seconds.parse::<f64>().map_err(|_| invalid())?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        Err(invalid())?
+    }
+    minutes_and_hours
+        .iter()
+        .rev()
+        .zip([60.0, 3600.0])
+        .try_fold(seconds, |total, (part, factor)| {
+            let n = part.parse::<u32>().map_err(|_| invalid())?;
+            Ok(total + f64::from(n) * factor)
+        })
+}
+
+impl Display for Evaluation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Pawns(pawns) => write!(f, "{pawns}"),
+            Self::Mate(moves) => write!(f, "#{moves}"),
+        }
+    }
+}
+
+/// A duration in seconds, displayed as `h:mm:ss`, with a fractional part if needed.
+struct Duration(f64);
+
+impl Display for Duration {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // Work in milliseconds to avoid rounding errors.
+        let milliseconds = (self.0 * 1000.0).round() as u64;
+        let (hours, milliseconds) = (milliseconds / 3_600_000, milliseconds % 3_600_000);
+        let (minutes, milliseconds) = (milliseconds / 60_000, milliseconds % 60_000);
+        let (seconds, milliseconds) = (milliseconds / 1000, milliseconds % 1000);
+        write!(f, "{hours}:{minutes:02}:{seconds:02}")?;
+        if milliseconds != 0 {
+            let fraction = format!("{milliseconds:03}");
+            write!(f, ".{}", fraction.trim_end_matches('0'))?
+        }
+        Ok(())
+    }
+}
+
+/// A PGN comment, with the commands it embeds.
+///
+/// Embedded commands are an extension to PGN used by lichess and ChessBase, among others. They are
+/// written `[%name arguments]` inside comments.
+#[derive(Debug, Clone, Default)]
+pub struct Comment {
+    /// The text of the comment, without the commands that were understood.
+    pub text: String,
+    /// The squares highlighted with `[%csl]` commands (e.g., `[%csl Gd4,Re5]`).
+    pub marked_squares: Vec<MarkedSquare>,
+    /// The arrows drawn with `[%cal]` commands (e.g., `[%cal Ge2e4]`).
+    pub arrows: Vec<Arrow>,
+    /// The remaining time on the clock of the player who just moved, in seconds, as given by a
+    /// `[%clk]` command (e.g., `[%clk 0:03:00]`).
+    pub clock: Option<f64>,
+    /// The time the last move took, in seconds, as given by an `[%emt]` command.
+    pub elapsed_time: Option<f64>,
+    /// The evaluation of the position, as given by an `[%eval]` command (e.g., `[%eval 0.34]`).
+    pub evaluation: Option<Evaluation>,
+}
+
+impl Comment {
+    /// Parses the arguments of a command, and adds the result to this comment.
+    ///
+    /// Returns `false` if the command is unknown or if its arguments are invalid. Such commands are
+    /// kept in the text of the comment.
+    fn apply_command(&mut self, name: &str, arguments: &str) -> bool {
+        let list = arguments
+            .split(',')
+            .map(str::trim)
+            .filter(|argument| !argument.is_empty());
+        match name {
+            "csl" => {
+                let marked_squares = list
+                    .map(|argument| {
+                        let (color, square) = split_color(argument)?;
+                        Some(MarkedSquare {
+                            color: color.parse().ok()?,
+                            square: square.parse().ok()?,
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>();
+                let Some(marked_squares) = marked_squares else {
+                    return false;
+                };
+                self.marked_squares.extend(marked_squares);
+            }
+            "cal" => {
+                let arrows = list
+                    .map(|argument| {
+                        let (color, squares) = split_color(argument)?;
+                        let (from, to) = squares.split_at_checked(2)?;
+                        Some(Arrow {
+                            color: color.parse().ok()?,
+                            from: from.parse().ok()?,
+                            to: to.parse().ok()?,
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>();
+                let Some(arrows) = arrows else {
+                    return false;
+                };
+                self.arrows.extend(arrows);
+            }
+            "clk" => match parse_duration(arguments) {
+                Ok(clock) => self.clock = Some(clock),
+                Err(_) => return false,
+            },
+            "emt" => match parse_duration(arguments) {
+                Ok(elapsed_time) => self.elapsed_time = Some(elapsed_time),
+                Err(_) => return false,
+            },
+            "eval" => match arguments.parse() {
+                Ok(evaluation) => self.evaluation = Some(evaluation),
+                Err(_) => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Splits a command argument into its leading color code and the rest.
+fn split_color(argument: &str) -> Option<(char, &str)> {
+    let mut chars = argument.chars();
+    Some((chars.next()?, chars.as_str()))
+}
+
+impl FromStr for Comment {
+    type Err = Error;
+
+    /// Parses the text of a comment, extracting the commands it embeds.
+    fn from_str(s: &str) -> crate::Result<Self> {
+        let mut comment = Self::default();
+        let mut remainder = s;
+        while let Some(start) = remainder.find("[%") {
+            let Some(length) = remainder[start..].find(']') else {
+                break;
+            };
+            let command = &remainder[start + 2..start + length];
+            let (name, arguments) = command
+                .split_once(char::is_whitespace)
+                .unwrap_or((command, ""));
+            let (before, after) = (&remainder[..start], &remainder[start + length + 1..]);
+            if comment.apply_command(name, arguments) {
+                comment.text.push_str(before);
+                // Avoid doubling the whitespace surrounding the command.
+                if comment.text.ends_with(char::is_whitespace) || comment.text.is_empty() {
+                    remainder = after.trim_start();
+                } else {
+                    remainder = after;
+                }
+            } else {
+                comment.text.push_str(&remainder[..start + length + 1]);
+                remainder = after;
+            }
+        }
+        comment.text.push_str(remainder);
+        // Line breaks in comments are not significant, so whitespace is normalized.
+        comment.text = comment
+            .text
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        Ok(comment)
+    }
+}
+
+impl Display for Comment {
+    /// Writes the comment, without braces. Commands are written before the text.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut commands = Vec::new();
+        if !self.marked_squares.is_empty() {
+            let arguments = self
+                .marked_squares
+                .iter()
+                .map(|m| format!("{}{}", m.color.name(), m.square))
+                .collect::<Vec<_>>();
+            commands.push(format!("[%csl {}]", arguments.join(",")));
+        }
+        if !self.arrows.is_empty() {
+            let arguments = self
+                .arrows
+                .iter()
+                .map(|a| format!("{}{}{}", a.color.name(), a.from, a.to))
+                .collect::<Vec<_>>();
+            commands.push(format!("[%cal {}]", arguments.join(",")));
+        }
+        if let Some(clock) = self.clock {
+            commands.push(format!("[%clk {}]", Duration(clock)));
+        }
+        if let Some(elapsed_time) = self.elapsed_time {
+            commands.push(format!("[%emt {}]", Duration(elapsed_time)));
+        }
+        if let Some(evaluation) = self.evaluation {
+            commands.push(format!("[%eval {evaluation}]"));
+        }
+        if !self.text.is_empty() {
+            commands.push(self.text.clone());
+        }
+        write!(f, "{}", commands.join(" "))
+    }
+}
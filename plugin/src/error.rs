@@ -0,0 +1,173 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// The kind of an [`Error`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ErrorKind {
+    /// The input is not valid Portable Game Notation.
+    Pgn,
+    /// The input is not valid Forsyth–Edwards Notation.
+    Fen,
+    /// A move is not written in a supported notation.
+    Notation,
+    /// A move is illegal or ambiguous.
+    IllegalMove,
+    /// The plugin was called with malformed arguments.
+    Internal,
+    /// Any other error, such as an invalid square.
+    Other,
+}
+
+/// The location of an error in the string it was found in.
+#[derive(Debug, Clone)]
+pub struct Location {
+    /// The line of the error, starting from 1.
+    pub line: usize,
+    /// The column of the error, in characters, starting from 1.
+    pub column: usize,
+    /// The line of the string the error was found in.
+    pub snippet: String,
+}
+
+/// An error, together with where it was found.
+///
+/// Locations are relative to the string that was being parsed when the error was found. For
+/// example, an error in the `FEN` tag of a PGN game is located in the value of the tag.
+#[derive(Debug, Clone)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// The byte offset of the error.
+    pub offset: Option<usize>,
+    /// The location of the error, computed from [`Self::offset`] by [`Self::with_source`].
+    ///
+    /// This is boxed to keep results small.
+    pub location: Option<Box<Location>>,
+    /// The index of the halfmove the error is about (see [`crate::model::Position::ply`]).
+    pub ply: Option<usize>,
+    /// The token the error is about (e.g., a move).
+    pub token: Option<String>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            offset: None,
+            location: None,
+            ply: None,
+            token: None,
+        }
+    }
+
+    /// Creates an error caused by malformed arguments passed to the plugin.
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+
+    /// Sets the kind of this error, unless it is already more specific than
+    /// [`ErrorKind::Other`].
+    pub fn in_context(mut self, kind: ErrorKind) -> Self {
+        if self.kind == ErrorKind::Other {
+            self.kind = kind;
+        }
+        self
+    }
+
+    /// Sets the byte offset of this error, unless it is already known.
+    pub fn at(mut self, offset: usize) -> Self {
+        self.offset.get_or_insert(offset);
+        self
+    }
+
+    /// Sets the halfmove this error is about, unless it is already known.
+    pub fn at_ply(mut self, ply: usize) -> Self {
+        self.ply.get_or_insert(ply);
+        self
+    }
+
+    /// Sets the token this error is about, unless it is already known.
+    pub fn with_token(mut self, token: impl Display) -> Self {
+        self.token.get_or_insert_with(|| token.to_string());
+        self
+    }
+
+    /// Computes the location of this error from its offset in the string it was found in.
+    ///
+    /// Does nothing if the offset is unknown or if the location is already known.
+    pub fn with_source(mut self, source: &str) -> Self {
+        if self.location.is_some() {
+            return self;
+        }
+        let Some(offset) = self.offset else {
+            return self;
+        };
+        let offset = (0..=offset.min(source.len()))
+            .rev()
+            .find(|&i| source.is_char_boundary(i))
+            .unwrap_or(0);
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[offset..]
+            .find('\n')
+            .map_or(source.len(), |i| offset + i);
+        self.location = Some(Box::new(Location {
+            line: source[..offset].matches('\n').count() + 1,
+            column: source[line_start..offset].chars().count() + 1,
+            snippet: source[line_start..line_end].trim_end_matches('\r').into(),
+        }));
+        self
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Self::new(ErrorKind::Other, message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Self::new(ErrorKind::Other, message)
+    }
+}
+
+impl Display for Error {
+    /// Writes the message of this error, followed by the line it was found in, with a caret under
+    /// the offending token if the location is known:
+    ///
+    /// ```text
+    /// invalid SAN: "Qxx"
+    ///  --> line 3, column 10
+    ///   |
+    /// 3 | 1. e4 e5 Qxx
+    ///   |          ^^^
+    /// ```
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        let Some(location) = &self.location else {
+            return Ok(());
+        };
+        let number = location.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let prefix = location
+            .snippet
+            .chars()
+            .take(location.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let width = self
+            .token
+            .as_ref()
+            .map_or(1, |token| token.chars().count().max(1));
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{gutter}--> line {}, column {}",
+            location.line, location.column
+        )?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{number} | {}", location.snippet)?;
+        write!(f, "{gutter} | {prefix}{}", "^".repeat(width))
+    }
+}
@@ -0,0 +1,79 @@
+use crate::game::{Line, is_rest_of_line_comment};
+use crate::tags::Tags;
+
+/// The tags of the Seven Tag Roster, in order, with the values to use when they are missing.
+pub const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+/// The maximum length of a line of movetext.
+const LINE_LENGTH: usize = 80;
+
+/// Writes a tag pair.
+///
+/// Values cannot contain ASCII control characters (e.g., line breaks), which are not allowed in
+/// PGN strings.
+fn write_tag_pair(pgn: &mut String, name: &str, value: &str) -> crate::Result<()> {
+    if value.chars().any(|c| c.is_ascii_control()) {
+        Err(format!(
+            "invalid value for tag {name} (ASCII control characters are not allowed): {value:?}"
+        ))?
+    }
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    pgn.push_str(&format!("[{name} \"{value}\"]\n"));
+    Ok(())
+}
+
+/// Writes a game in PGN export format, as defined in section 8 of [the specification].
+///
+/// The tags of the Seven Tag Roster are written first, followed by the other tags in ASCII order.
+/// The `Result` tag is always set to `result`, which must be a game termination marker.
+///
+/// [the specification]: https://ia902908.us.archive.org/26/items/pgn-standard-1994-03-12/PGN_standard_1994-03-12.txt
+pub fn export(tags: &Tags, line: &Line, result: &str) -> crate::Result<String> {
+    let mut pgn = String::new();
+    for (name, default) in SEVEN_TAG_ROSTER {
+        let value = match name {
+            "Result" => result,
+            _ => tags.get(name).unwrap_or(default),
+        };
+        write_tag_pair(&mut pgn, name, value)?;
+    }
+    let mut other_tags = tags
+        .iter()
+        .filter(|(name, _)| SEVEN_TAG_ROSTER.iter().all(|(n, _)| n != name))
+        .collect::<Vec<_>>();
+    other_tags.sort_by_key(|(name, _)| *name);
+    for (name, value) in other_tags {
+        write_tag_pair(&mut pgn, name, value)?;
+    }
+    pgn.push('\n');
+
+    let mut tokens = line.pgn_tokens()?;
+    tokens.push(result.to_string());
+    let mut line_length = 0;
+    for token in tokens {
+        if line_length != 0 && line_length + 1 + token.chars().count() > LINE_LENGTH {
+            pgn.push('\n');
+            line_length = 0;
+        }
+        if line_length != 0 {
+            pgn.push(' ');
+            line_length += 1;
+        }
+        pgn.push_str(&token);
+        line_length += token.chars().count();
+        if is_rest_of_line_comment(&token) {
+            pgn.push('\n');
+            line_length = 0;
+        }
+    }
+    pgn.push('\n');
+    Ok(pgn)
+}
@@ -1,8 +1,11 @@
+use crate::error::{Error, ErrorKind};
 use crate::model::{
-    Board, CastlingAvailabilities, Color, File, Piece, PieceKind, Position, Rank, Square,
+    Board, CastlingAvailabilities, Color, File, Piece, PieceKind, Position, Rank, Side, Square,
     SquareContent,
 };
-use crate::utils::Name;
+use crate::san::{can_capture_en_passant, is_in_check};
+use crate::utils::{CharExt, Finite};
+use crate::zobrist::rehash;
 use std::str::FromStr;
 
 fn parse_piece(fen: char) -> crate::Result<Piece> {
@@ -24,31 +27,125 @@ fn parse_piece(fen: char) -> crate::Result<Piece> {
 }
 
 fn parse_board(fen: &str) -> crate::Result<Board> {
+    let fen_ranks = fen.split('/').collect::<Vec<_>>();
+    if fen_ranks.len() != 8 {
+        Err(format!(
+            "invalid FEN: expected 8 ranks, found {}",
+            fen_ranks.len()
+        ))?
+    }
     let mut squares = [[SquareContent::Empty; 8]; 8];
-    for (rank_index, fen_rank) in fen.split('/').enumerate() {
+    for (rank_index, fen_rank) in fen_ranks.into_iter().enumerate() {
+        // Ranks are given from the eighth to the first.
+        let rank_number = 8 - rank_index;
         let mut file_index = 0;
         for c in fen_rank.chars() {
-            if let Some(n) = c.to_digit(10) {
-                file_index += n as usize
-            } else {
-                squares[rank_index][file_index] = SquareContent::Piece(parse_piece(c)?);
-                file_index += 1;
+            let (width, content) = match c.to_digit(10) {
+                Some(n @ 1..=8) => (n as usize, SquareContent::Empty),
+                Some(_) => Err(format!(
+                    "invalid FEN: invalid number of empty squares in rank {rank_number}: {c}"
+                ))?,
+                None => (1, SquareContent::Piece(parse_piece(c)?)),
+            };
+            if file_index + width > 8 {
+                Err(format!(
+                    "invalid FEN: rank {rank_number} has more than 8 squares"
+                ))?
             }
+            squares[rank_index][file_index] = content;
+            file_index += width;
+        }
+        if file_index != 8 {
+            Err(format!(
+                "invalid FEN: rank {rank_number} has {file_index} squares instead of 8"
+            ))?
         }
     }
     squares.reverse();
     Ok(Board::new(squares))
 }
 
-fn parse_castling_availabilities(fen: &str) -> crate::Result<CastlingAvailabilities> {
-    Ok(CastlingAvailabilities {
-        white_kingside: fen.contains('K'),
-        white_queenside: fen.contains('Q'),
-        black_kingside: fen.contains('k'),
-        black_queenside: fen.contains('q'),
+/// Returns the file of the outermost rook of a player on a side of their king, if there is one.
+fn outermost_rook_file(board: &Board, color: Color, side: Side) -> Option<File> {
+    let king_file = board.home_king_file(color)?;
+    let rook = Piece::new(color, PieceKind::Rook);
+    let mut files = File::values()
+        .into_iter()
+        .filter(|&file| board.get(Square::new(file, color.home_rank())).is(rook));
+    match side {
+        Side::King => files.rfind(|file| file.index() > king_file.index()),
+        Side::Queen => files.find(|file| file.index() < king_file.index()),
+    }
+}
+
+/// Returns the file of the rook that `K` or `Q` refers to in X-FEN.
+///
+/// If there is no such rook, the file a rook starts on in standard chess is returned, so that
+/// `KQkq` can be used for any position, as in standard FEN.
+fn x_fen_rook_file(board: &Board, color: Color, side: Side) -> File {
+    outermost_rook_file(board, color, side).unwrap_or(match side {
+        Side::King => File::H,
+        Side::Queen => File::A,
     })
 }
 
+/// Parses castling availabilities, written in standard FEN, X-FEN, or Shredder-FEN.
+///
+/// `K` and `Q` refer to the outermost rook on each side of the king, and file letters (e.g., `H`
+/// or `A`) refer to the rook on that file. Lower-case letters are used for Black.
+///
+/// Also returns whether file letters are used.
+fn parse_castling_availabilities(
+    fen: &str,
+    board: &Board,
+) -> crate::Result<(CastlingAvailabilities, bool)> {
+    let mut castling_availabilities = CastlingAvailabilities::NONE;
+    let mut uses_files = false;
+    if fen == "-" {
+        return Ok((castling_availabilities, uses_files));
+    }
+    if fen.is_empty() {
+        Err("invalid FEN: empty castling availabilities")?
+    }
+    for c in fen.chars() {
+        let color = match c.is_ascii_uppercase() {
+            true => Color::White,
+            false => Color::Black,
+        };
+        let (side, file) = match c.to_ascii_lowercase() {
+            'k' => (Side::King, x_fen_rook_file(board, color, Side::King)),
+            'q' => (Side::Queen, x_fen_rook_file(board, color, Side::Queen)),
+            'a'..='h' => {
+                uses_files = true;
+                let file = c.to_ascii_lowercase().parse::<File>()?;
+                // Without a king on the first rank, sides are relative to the usual king file.
+                let king_file = board.home_king_file(color).unwrap_or(File::E);
+                if file == king_file {
+                    Err(format!(
+                        "invalid FEN: invalid castling availability (the king is on this file): {c}"
+                    ))?
+                }
+                match file.index() > king_file.index() {
+                    true => (Side::King, file),
+                    false => (Side::Queen, file),
+                }
+            }
+            _ => Err(format!("invalid FEN: invalid castling availability: {c}"))?,
+        };
+        let availability = castling_availabilities.get_mut(color, side);
+        match availability {
+            Some(previous) if *previous == file => Err(format!(
+                "invalid FEN: castling availability {c} is given twice"
+            ))?,
+            Some(_) => Err(format!(
+                "invalid FEN: castling availability {c} conflicts with another one on the same side"
+            ))?,
+            None => *availability = Some(file),
+        }
+    }
+    Ok((castling_availabilities, uses_files))
+}
+
 fn parse_en_passant_target_square(fen: &str) -> crate::Result<Option<Square>> {
     if fen == "-" {
         return Ok(None);
@@ -56,58 +153,240 @@ fn parse_en_passant_target_square(fen: &str) -> crate::Result<Option<Square>> {
     Ok(Some(fen.parse()?))
 }
 
+/// Checks that an en passant target square is on the rank the active player can capture on.
+///
+/// With [`Validation::Legality`], also checks that it is consistent with the position: the pawn
+/// that just moved two squares must be in front of it, and the squares it moved through must be
+/// empty.
+fn validate_en_passant_target_square(
+    board: &Board,
+    active: Color,
+    square: Square,
+    validation: Validation,
+) -> crate::Result<()> {
+    if square.rank() != active.en_passant_target_rank() {
+        Err(format!(
+            "invalid FEN: illegal en passant target square (invalid rank): {square}"
+        ))?
+    }
+    if validation == Validation::Syntax {
+        return Ok(());
+    }
+    // The square the pawn moved from.
+    let origin = Square::new(
+        square.file(),
+        match active {
+            Color::White => Rank::Seven,
+            Color::Black => Rank::Two,
+        },
+    );
+    let pawn = Square::new(square.file(), active.en_passant_capture_rank());
+    if board.get(square).is_occupied() || board.get(origin).is_occupied() {
+        Err(format!(
+            "invalid FEN: illegal en passant target square (a pawn cannot have just moved through it): {square}"
+        ))?
+    }
+    if !board
+        .get(pawn)
+        .is(Piece::new(active.flip(), PieceKind::Pawn))
+    {
+        Err(format!(
+            "invalid FEN: illegal en passant target square (no pawn on {pawn}): {square}"
+        ))?
+    }
+    Ok(())
+}
+
+/// Checks that the pieces of a position could appear in a game: each player has exactly one king,
+/// there are no pawns on the first and eighth ranks, and the player who just moved is not in check.
+fn validate_position(position: &Position) -> crate::Result<()> {
+    for color in [Color::White, Color::Black] {
+        let king = Piece::new(color, PieceKind::King);
+        let kings = position.board.pieces(king).count();
+        if kings != 1 {
+            Err(format!(
+                "invalid FEN: {} should have exactly one king, but has {kings}",
+                color_name(color)
+            ))?
+        }
+    }
+    for square in Square::all() {
+        if matches!(square.rank(), Rank::One | Rank::Eight)
+            && let SquareContent::Piece(piece) = position.at(square)
+            && piece.kind == PieceKind::Pawn
+        {
+            Err(format!(
+                "invalid FEN: pawns cannot be on the first or eighth rank, but there is one on {square}"
+            ))?
+        }
+    }
+    let waiting = Position {
+        active: position.active.flip(),
+        ..position.clone()
+    };
+    if is_in_check(&waiting) {
+        Err(format!(
+            "invalid FEN: {} is in check, but it is not their turn",
+            color_name(waiting.active)
+        ))?
+    }
+    Ok(())
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "White",
+        Color::Black => "Black",
+    }
+}
+
 fn parse_int(fen: &str) -> crate::Result<u32> {
-    u32::from_str(fen).map_err(|err| err.to_string())
+    Ok(u32::from_str(fen).map_err(|err| err.to_string())?)
+}
+
+/// How thoroughly a FEN string is validated.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Validation {
+    /// Only the syntax of each field is checked, so that any diagram can be parsed.
+    Syntax,
+    /// The position must also be one that could appear in a game (see [`validate_position`]), and
+    /// the en passant target square must be consistent with it (see
+    /// [`validate_en_passant_target_square`]).
+    Legality,
 }
 
-/// Parses Forsyth–Edwards Notation (FEN) into a position.
+/// Parses Forsyth–Edwards Notation (FEN) into a position that could appear in a game.
+///
+/// Errors are located in `fen`, and point at the field that could not be parsed.
 pub fn parse_fen(fen: &str) -> crate::Result<Position> {
-    let mut parts = fen.split(' ');
+    parse_fen_with(fen, Validation::Legality)
+}
 
-    let board = parse_board(parts.next().ok_or("invalid FEN: missing board info")?)?;
+/// Parses Forsyth–Edwards Notation (FEN) into a position, validating it as specified.
+///
+/// Errors are located in `fen`, and point at the field that could not be parsed.
+pub fn parse_fen_with(fen: &str, validation: Validation) -> crate::Result<Position> {
+    parse_fields(fen, validation).map_err(|error| error.in_context(ErrorKind::Fen).with_source(fen))
+}
 
-    let active = match parts.next() {
-        Some("w") => Color::White,
-        Some("b") => Color::Black,
-        Some(p) => Err(format!("invalid active player: {p}"))?,
-        None => return Ok(Position::default_with_board(board)),
-    };
+/// Returns a function attaching the location of a field to the errors found when parsing it.
+fn locate(offset: usize, field: &str) -> impl FnOnce(Error) -> Error {
+    move |error| error.at(offset).with_token(field)
+}
 
-    let castling_availabilities = parse_castling_availabilities(
-        parts
+fn parse_fields(fen: &str, validation: Validation) -> crate::Result<Position> {
+    // Fields are separated by single spaces. Other whitespace is rejected rather than skipped,
+    // because FEN strings are also split at spaces on the Typst side.
+    for (offset, c) in fen.char_indices() {
+        if c.is_ascii_whitespace()
+            && (c != ' ' || offset == 0 || offset + 1 == fen.len() || fen[..offset].ends_with(' '))
+        {
+            Err(Error::from(
+                "invalid FEN: unexpected whitespace (fields should be separated by single spaces)",
+            )
+            .at(offset))?
+        }
+    }
+    // Each field, together with its byte offset.
+    let mut fields = fen.split(' ').scan(0, |offset, field| {
+        let start = *offset;
+        *offset += field.len() + 1;
+        Some((start, field))
+    });
+    // Errors about missing fields point at the end of the string.
+    let mut next_field = |message: &str| {
+        fields
             .next()
-            .ok_or("invalid FEN: missing castling availabilities")?,
-    )?;
+            .ok_or_else(|| Error::from(message).at(fen.len()))
+    };
 
-    let en_passant_target_square = parse_en_passant_target_square(
-        parts
-            .next()
-            .ok_or("invalid FEN: missing en passant target square")?,
-    )?;
-    if let Some(square) = en_passant_target_square
-        && square.rank() != active.en_passant_target_rank()
-    {
-        Err(format!(
-            "invalid FEN: illegal en passant target square (invalid rank): {}",
-            square
-        ))?
+    let (offset, field) = next_field("invalid FEN: missing board info")?;
+    let board = parse_board(field).map_err(locate(offset, field))?;
+    let board_location = locate(offset, field);
+
+    let active = match next_field("") {
+        Ok((_, "w")) => Color::White,
+        Ok((_, "b")) => Color::Black,
+        Ok((offset, field)) => Err(locate(offset, field)(
+            format!("invalid active player: {field}").into(),
+        ))?,
+        Err(_) => {
+            let position = Position::default_with_board(board);
+            if validation == Validation::Legality {
+                validate_position(&position).map_err(board_location)?;
+            }
+            return Ok(position);
+        }
+    };
+
+    let (offset, field) = next_field("invalid FEN: missing castling availabilities")?;
+    let (castling_availabilities, castling_uses_files) =
+        parse_castling_availabilities(field, &board).map_err(locate(offset, field))?;
+
+    let (offset, field) = next_field("invalid FEN: missing en passant target square")?;
+    let en_passant_target_square =
+        parse_en_passant_target_square(field).map_err(locate(offset, field))?;
+    if let Some(square) = en_passant_target_square {
+        validate_en_passant_target_square(&board, active, square, validation)
+            .map_err(locate(offset, field))?;
     }
 
-    let halfmove = parse_int(parts.next().ok_or("invalid FEN: missing fullmove")?)?;
+    let (offset, field) = next_field("invalid FEN: missing halfmove clock")?;
+    let halfmove = parse_int(field).map_err(locate(offset, field))?;
 
-    let fullmove = parse_int(parts.next().ok_or("invalid FEN: missing fullmove")?)?;
+    let (offset, field) = next_field("invalid FEN: missing fullmove")?;
+    let fullmove = parse_int(field).map_err(locate(offset, field))?;
+    if fullmove == 0 {
+        Err(locate(offset, field)(
+            "invalid FEN: the fullmove number should be at least 1".into(),
+        ))?
+    }
 
-    if parts.next().is_some() {
-        Err("invalid FEN: too many parts")?
+    if let Ok((offset, field)) = next_field("") {
+        Err(locate(offset, field)("invalid FEN: too many parts".into()))?
     }
 
-    Ok(Position {
+    let mut position = Position {
         board,
         active,
         castling_availabilities,
         en_passant_target_file: en_passant_target_square.map(Square::file),
         halfmove,
         fullmove,
+        chess960: false,
+        zobrist: 0,
+    };
+    rehash(&mut position);
+    if validation == Validation::Legality {
+        validate_position(&position).map_err(board_location)?;
+    }
+    // `KQkq` is also valid in standard chess, where it cannot be used to castle with pieces that
+    // are not on their usual squares. Only files select Chess960.
+    Ok(Position {
+        chess960: castling_uses_files && has_chess960_castling(&position),
+        ..position
+    })
+}
+
+/// Tests whether a player can castle with a king or a rook that is not on the square it starts on
+/// in standard chess, which is only allowed in Chess960.
+fn has_chess960_castling(position: &Position) -> bool {
+    [Color::White, Color::Black].into_iter().any(|color| {
+        let Some(king_file) = position.board.home_king_file(color) else {
+            return false;
+        };
+        [(Side::King, File::H), (Side::Queen, File::A)]
+            .into_iter()
+            .any(|(side, standard_file)| {
+                position
+                    .castling_availabilities
+                    .get(color, side)
+                    .is_some_and(|file| {
+                        let rook = Square::new(file, color.home_rank());
+                        position.at(rook).is(Piece::new(color, PieceKind::Rook))
+                            && (file != standard_file || king_file != File::E)
+                    })
+            })
     })
 }
 
@@ -120,12 +399,12 @@ fn fen_piece(piece: Piece) -> String {
     s
 }
 
-fn fen_board(board: Board) -> String {
+fn fen_board(board: &Board) -> String {
     let mut s = String::new();
     for r in (0..8).rev() {
         let mut empty_streak = 0;
         for f in 0..8 {
-            match board[Square::new(File::new(f).unwrap(), Rank::new(r).unwrap())] {
+            match board.get(Square::new(File::new(f).unwrap(), Rank::new(r).unwrap())) {
                 SquareContent::Empty => empty_streak += 1,
                 SquareContent::Piece(piece) => {
                     if empty_streak > 0 {
@@ -153,19 +432,50 @@ fn fen_color(color: Color) -> &'static str {
     }
 }
 
-fn fen_castling_availabilities(castling_availabilities: CastlingAvailabilities) -> String {
-    let mut s = String::new();
-    if castling_availabilities.white_kingside {
-        s.push('K')
-    }
-    if castling_availabilities.white_queenside {
-        s.push('Q')
-    }
-    if castling_availabilities.black_kingside {
-        s.push('k')
+/// A notation for castling availabilities in FEN.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CastlingNotation {
+    /// X-FEN, which uses `KQkq` like standard FEN, and file letters only when `K` or `Q` would
+    /// refer to another rook.
+    XFen,
+    /// Shredder-FEN, which always uses file letters (e.g., `HAha`).
+    Shredder,
+}
+
+/// Writes the availability of a player to castle with the rook on a file.
+fn fen_castling_availability(
+    position: &Position,
+    color: Color,
+    side: Side,
+    file: File,
+    notation: CastlingNotation,
+) -> String {
+    let mut availability = if notation == CastlingNotation::XFen
+        && x_fen_rook_file(&position.board, color, side) == file
+    {
+        match side {
+            Side::King => "k".to_string(),
+            Side::Queen => "q".to_string(),
+        }
+    } else {
+        file.to_string()
+    };
+    if color == Color::White {
+        availability.make_ascii_uppercase()
     }
-    if castling_availabilities.black_queenside {
-        s.push('q')
+    availability
+}
+
+fn fen_castling_availabilities(position: &Position, notation: CastlingNotation) -> String {
+    let mut s = String::new();
+    for color in [Color::White, Color::Black] {
+        for side in [Side::King, Side::Queen] {
+            if let Some(file) = position.castling_availabilities.get(color, side) {
+                s.push_str(&fen_castling_availability(
+                    position, color, side, file, notation,
+                ))
+            }
+        }
     }
     if s.is_empty() {
         s.push('-')
@@ -174,17 +484,385 @@ fn fen_castling_availabilities(castling_availabilities: CastlingAvailabilities)
 }
 
 /// Converts a position to Forsyth–Edwards Notation (FEN).
+///
+/// Castling availabilities are written in X-FEN, which is the same as standard FEN unless the
+/// rooks do not start in the corners.
 pub fn fen(position: Position) -> String {
+    fen_with(position, CastlingNotation::XFen)
+}
+
+/// Converts a position to Forsyth–Edwards Notation (FEN), writing castling availabilities in the
+/// given notation.
+pub fn fen_with(position: Position, notation: CastlingNotation) -> String {
     format!(
         "{} {} {} {} {} {}",
-        fen_board(position.board),
+        fen_board(&position.board),
         fen_color(position.active),
-        fen_castling_availabilities(position.castling_availabilities),
+        fen_castling_availabilities(&position, notation),
         match position.en_passant_target_file {
             None => "-".to_string(),
-            Some(square) => square.name(),
+            Some(file) => Square::new(file, position.active.en_passant_target_rank()).to_string(),
         },
         position.halfmove,
         position.fullmove,
     )
 }
+
+/// Rewrites a position so that equivalent positions have the same FEN.
+///
+/// The en passant target square is removed if no legal en passant capture exists, and castling
+/// availabilities are removed if the king or the rook has left its home square. If
+/// `reset_move_counters` is true, the halfmove clock is reset to 0 and the fullmove number to 1.
+///
+/// Returns the canonical position, together with a description of each change that was made.
+pub fn canonicalize(position: &Position, reset_move_counters: bool) -> (Position, Vec<String>) {
+    let mut canonical = position.clone();
+    let mut changes = Vec::new();
+
+    if let Some(file) = position.en_passant_target_file
+        && !can_capture_en_passant(position)
+    {
+        canonical.en_passant_target_file = None;
+        changes.push(format!(
+            "removed en passant target square {} (no legal en passant capture)",
+            Square::new(file, position.active.en_passant_target_rank())
+        ));
+    }
+
+    for color in [Color::White, Color::Black] {
+        for side in [Side::King, Side::Queen] {
+            let Some(file) = position.castling_availabilities.get(color, side) else {
+                continue;
+            };
+            let rook = Square::new(file, color.home_rank());
+            let reason = match position.board.home_king_file(color) {
+                None => format!("the king is not on rank {}", color.home_rank()),
+                Some(_) if !position.at(rook).is(Piece::new(color, PieceKind::Rook)) => {
+                    format!("the rook is not on {rook}")
+                }
+                Some(king_file) if (file.index() > king_file.index()) != (side == Side::King) => {
+                    format!("the rook on {rook} is on the wrong side of the king")
+                }
+                Some(_) => continue,
+            };
+            *canonical.castling_availabilities.get_mut(color, side) = None;
+            changes.push(format!(
+                "removed castling availability {} ({reason})",
+                fen_castling_availability(position, color, side, file, CastlingNotation::XFen)
+            ));
+        }
+    }
+
+    if reset_move_counters {
+        if position.halfmove != 0 {
+            canonical.halfmove = 0;
+            changes.push(format!(
+                "reset halfmove clock from {} to 0",
+                position.halfmove
+            ));
+        }
+        if position.fullmove != 1 {
+            canonical.fullmove = 1;
+            changes.push(format!(
+                "reset fullmove number from {} to 1",
+                position.fullmove
+            ));
+        }
+    }
+
+    rehash(&mut canonical);
+    (canonical, changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STARTING_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    /// Returns the message of the error found when parsing `fen`.
+    fn error(fen: &str) -> String {
+        parse_fen(fen).unwrap_err().message
+    }
+
+    #[test]
+    fn valid_positions() {
+        assert_eq!(
+            fen(parse_fen(STARTING_POSITION).unwrap()),
+            STARTING_POSITION
+        );
+        let after_e4 = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        assert_eq!(fen(parse_fen(after_e4).unwrap()), after_e4);
+        let board_only = parse_fen("4k3/8/8/8/8/8/8/4K3").unwrap();
+        assert_eq!(fen(board_only), "4k3/8/8/8/8/8/8/4K3 w KQkq - 0 1");
+    }
+
+    #[test]
+    fn malformed_board() {
+        assert_eq!(
+            error("8/8/8/8/8/8/8 w - - 0 1"),
+            "invalid FEN: expected 8 ranks, found 7"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/8/4K3 w - - 0 1"),
+            "invalid FEN: expected 8 ranks, found 9"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3P w - - 0 1"),
+            "invalid FEN: rank 1 has more than 8 squares"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3pppppppppppp w - - 0 1"),
+            "invalid FEN: rank 1 has more than 8 squares"
+        );
+        assert_eq!(
+            error("4k3/7/8/8/8/8/8/4K3 w - - 0 1"),
+            "invalid FEN: rank 7 has 7 squares instead of 8"
+        );
+        assert_eq!(
+            error("4k3/9/8/8/8/8/8/4K3 w - - 0 1"),
+            "invalid FEN: invalid number of empty squares in rank 7: 9"
+        );
+        assert_eq!(
+            error("4k3/08/8/8/8/8/8/4K3 w - - 0 1"),
+            "invalid FEN: invalid number of empty squares in rank 7: 0"
+        );
+        assert_eq!(error("4k3/8/8/8/4x3/8/8/4K3 w - - 0 1"), "invalid piece: x");
+    }
+
+    #[test]
+    fn impossible_positions() {
+        assert_eq!(
+            error("8/8/8/8/8/8/8/4K3 w - - 0 1"),
+            "invalid FEN: Black should have exactly one king, but has 0"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"),
+            "invalid FEN: White should have exactly one king, but has 2"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K2P w - - 0 1"),
+            "invalid FEN: pawns cannot be on the first or eighth rank, but there is one on h1"
+        );
+        assert_eq!(
+            error("p3k3/8/8/8/8/8/8/4K3 w - - 0 1"),
+            "invalid FEN: pawns cannot be on the first or eighth rank, but there is one on a8"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K2r b - - 0 1"),
+            "invalid FEN: White is in check, but it is not their turn"
+        );
+        assert_eq!(
+            error("4k2R/8/8/8/8/8/8/4K3"),
+            "invalid FEN: Black is in check, but it is not their turn"
+        );
+    }
+
+    #[test]
+    fn diagrams() {
+        // Positions that could not appear in a game are still valid diagrams.
+        let diagram = |fen: &str| parse_fen_with(fen, Validation::Syntax).map(super::fen);
+        for fen in [
+            "8/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K2P w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K2r b - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 b - e3 0 1",
+        ] {
+            assert_eq!(diagram(fen).unwrap(), fen);
+        }
+        assert!(diagram("8/8/8/8/8/8/8 w - - 0 1").is_err());
+        assert!(diagram("4k3/8/8/8/8/8/8/4K3 w - e3 0 1").is_err());
+    }
+
+    #[test]
+    fn malformed_fields() {
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 x - - 0 1"),
+            "invalid active player: x"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w KX - 0 1"),
+            "invalid FEN: invalid castling availability: X"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w KK - 0 1"),
+            "invalid FEN: castling availability K is given twice"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w KX"),
+            "invalid FEN: invalid castling availability: X"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w - - 0 0"),
+            "invalid FEN: the fullmove number should be at least 1"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w - - 0 1 x"),
+            "invalid FEN: too many parts"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w - -"),
+            "invalid FEN: missing halfmove clock"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w - - -1 1"),
+            "invalid digit found in string"
+        );
+    }
+
+    #[test]
+    fn en_passant_target_square() {
+        assert_eq!(
+            error("4k3/8/8/8/4P3/8/8/4K3 b - e4 0 1"),
+            "invalid FEN: illegal en passant target square (invalid rank): e4"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 b - e3 0 1"),
+            "invalid FEN: illegal en passant target square (no pawn on e4): e3"
+        );
+        assert_eq!(
+            error("4k3/8/8/8/4P3/4N3/8/4K3 b - e3 0 1"),
+            "invalid FEN: illegal en passant target square (a pawn cannot have just moved through it): e3"
+        );
+        assert_eq!(error("4k3/8/8/8/4P3/8/8/4K3 b - e9 0 1"), "invalid rank: 9");
+    }
+
+    #[test]
+    fn error_location() {
+        let error = parse_fen("4k3/8/8/8/8/8/8/4K3 w KX - 0 1").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Fen);
+        assert_eq!(error.token.as_deref(), Some("KX"));
+        let location = error.location.unwrap();
+        assert_eq!((location.line, location.column), (1, 23));
+
+        let error = parse_fen("4k3/8/8/8/8/8/8/4K3 w").unwrap_err();
+        assert_eq!(error.location.unwrap().column, 22);
+    }
+
+    #[test]
+    fn whitespace() {
+        let whitespace_error =
+            "invalid FEN: unexpected whitespace (fields should be separated by single spaces)";
+        for fen in [
+            "4k3/8/8/8/8/8/8/4K3 w  - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1 ",
+            " 4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3\tw - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1\n",
+        ] {
+            assert_eq!(error(fen), whitespace_error);
+        }
+        let error = parse_fen("4k3/8/8/8/8/8/8/4K3 w  - - 0 1").unwrap_err();
+        assert_eq!(error.location.unwrap().column, 23);
+    }
+
+    /// Canonicalizes `fen`, and returns the resulting FEN and changes.
+    fn canonical(fen: &str, reset_move_counters: bool) -> (String, Vec<String>) {
+        let (position, changes) = canonicalize(&parse_fen(fen).unwrap(), reset_move_counters);
+        (super::fen(position), changes)
+    }
+
+    #[test]
+    fn canonicalization() {
+        assert_eq!(
+            canonical(STARTING_POSITION, true),
+            (STARTING_POSITION.to_string(), Vec::new())
+        );
+        assert_eq!(
+            canonical(
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+                false
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1".to_string(),
+                vec![
+                    "removed en passant target square e3 (no legal en passant capture)".to_string()
+                ]
+            )
+        );
+        let en_passant = "4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1";
+        assert_eq!(canonical(en_passant, false).0, en_passant);
+        // The capture would expose the black king to the rook.
+        assert_eq!(
+            canonical("8/8/8/8/k2pP2R/8/8/4K3 b - e3 0 1", false).0,
+            "8/8/8/8/k2pP2R/8/8/4K3 b - - 0 1"
+        );
+        assert_eq!(
+            canonical("r3k2r/8/8/8/8/8/8/4K3 w KQkq - 12 40", true),
+            (
+                "r3k2r/8/8/8/8/8/8/4K3 w kq - 0 1".to_string(),
+                vec![
+                    "removed castling availability K (the rook is not on h1)".to_string(),
+                    "removed castling availability Q (the rook is not on a1)".to_string(),
+                    "reset halfmove clock from 12 to 0".to_string(),
+                    "reset fullmove number from 40 to 1".to_string(),
+                ]
+            )
+        );
+        assert_eq!(
+            canonical("r6r/4k3/8/8/8/8/8/R3K2R b KQkq - 0 1", false).1,
+            vec![
+                "removed castling availability k (the king is not on rank 8)".to_string(),
+                "removed castling availability q (the king is not on rank 8)".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn castling_notations() {
+        let shredder = |fen: &str| fen_with(parse_fen(fen).unwrap(), CastlingNotation::Shredder);
+        assert_eq!(
+            shredder(STARTING_POSITION),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1"
+        );
+        let chess960 = "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w KQkq - 0 1";
+        assert_eq!(fen(parse_fen(chess960).unwrap()), chess960);
+        assert_eq!(
+            shredder(chess960),
+            "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w GEge - 0 1"
+        );
+        assert_eq!(
+            fen(parse_fen("bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w GEge - 0 1").unwrap()),
+            chess960
+        );
+        // Only files select Chess960 castling, as `KQkq` is also used in standard chess.
+        assert!(!parse_fen(chess960).unwrap().chess960);
+        assert!(
+            !parse_fen("4k3/8/8/8/8/8/8/5K1R w KQkq - 0 1")
+                .unwrap()
+                .chess960
+        );
+        assert!(
+            !parse_fen(STARTING_POSITION.replace("KQkq", "HAha").as_str())
+                .unwrap()
+                .chess960
+        );
+        assert!(
+            parse_fen("bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w GEge - 0 1")
+                .unwrap()
+                .chess960
+        );
+        // `K` would refer to the rook on h1.
+        let inner_rook = "4k3/8/8/8/8/8/8/4KBRR w GQ - 0 1";
+        assert_eq!(fen(parse_fen(inner_rook).unwrap()), inner_rook);
+        assert_eq!(
+            parse_fen(inner_rook)
+                .unwrap()
+                .castling_availabilities
+                .white_kingside,
+            Some(File::G)
+        );
+        assert_eq!(
+            parse_fen("4k3/8/8/8/8/8/8/4KBRR w KG - 0 1")
+                .unwrap_err()
+                .message,
+            "invalid FEN: castling availability G conflicts with another one on the same side"
+        );
+        assert_eq!(
+            parse_fen("4k3/8/8/8/8/8/8/4K2R w E - 0 1")
+                .unwrap_err()
+                .message,
+            "invalid FEN: invalid castling availability (the king is on this file): E"
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use crate::comment::{Arrow, Comment, Evaluation, MarkedSquare};
+use crate::fen::fen;
+use crate::json::Json;
+use crate::model::{Color, Movement, Position};
+use crate::nag::Nag;
+use crate::san::{AnnotatedAlgebraicTurn, LegalMove, Mark};
+use crate::tags::TimeControl;
+use crate::termination::{Termination, termination};
+use crate::utils::Name;
+
+/// Tests whether a token of movetext is a rest of line comment, which starts with a semicolon and
+/// ends at the end of the line.
+pub fn is_rest_of_line_comment(token: &str) -> bool {
+    token.starts_with(';')
+}
+
+/// Writes comments as tokens of movetext (see [`Line::pgn_tokens`]).
+fn push_comment_tokens(tokens: &mut Vec<String>, comments: &[Comment]) -> crate::Result<()> {
+    for comment in comments {
+        let comment = comment.to_string();
+        if !comment.contains('}') {
+            let comment = format!("{{{comment}}}");
+            tokens.extend(comment.split(' ').map(String::from));
+        } else if comment.contains('\n') {
+            Err(format!(
+                "cannot write comment in PGN (it contains both a closing brace and a line break): {comment:?}"
+            ))?
+        } else {
+            tokens.push(format!(";{comment}"));
+        }
+    }
+    Ok(())
+}
+
+/// A sequence of moves, together with the successive positions they lead to.
+#[derive(Debug)]
+pub struct Line {
+    /// The successive positions of the line, starting with the position the line starts from.
+    positions: Vec<Position>,
+    /// For a variation, the positions of the game that precede the position it starts from.
+    ///
+    /// They are only used to detect repetitions.
+    previous_positions: Vec<Position>,
+    movements: Vec<Movement>,
+    sans: Vec<AnnotatedAlgebraicTurn>,
+    /// For each move, the check or checkmate mark that was written for it, if any.
+    ///
+    /// This is not necessarily consistent with the canonical SAN of the move.
+    check_marks: Vec<Option<Mark>>,
+    /// For each move, its numeric annotation glyphs.
+    nags: Vec<Vec<Nag>>,
+    /// For each position, the comments about it.
+    ///
+    /// The comments about the first position precede the first move. The other comments follow
+    /// the move leading to the corresponding position.
+    comments: Vec<Vec<Comment>>,
+    /// For each move, the alternative lines that were given for it.
+    variations: Vec<Vec<Line>>,
+}
+
+impl Line {
+    pub fn new(starting_position: Position) -> Self {
+        Self {
+            positions: vec![starting_position],
+            previous_positions: Vec::new(),
+            movements: Vec::new(),
+            sans: Vec::new(),
+            check_marks: Vec::new(),
+            nags: Vec::new(),
+            comments: vec![Vec::new()],
+            variations: Vec::new(),
+        }
+    }
+
+    /// Creates an empty line starting from the position at the end of this line, for a variation.
+    ///
+    /// The positions of this line are kept as the history of the new line, so that repetitions of
+    /// positions that appeared before the variation are detected.
+    pub fn branch(&self) -> Self {
+        // There is always at least one position.
+        let (last, previous) = self.positions.split_last().unwrap();
+        Self {
+            previous_positions: [&self.previous_positions[..], previous].concat(),
+            ..Self::new(last.clone())
+        }
+    }
+
+    /// Returns the position this line starts from.
+    pub fn first_position(&self) -> &Position {
+        // There is always at least one position.
+        &self.positions[0]
+    }
+
+    /// Returns the position at the end of this line.
+    pub fn last_position(&self) -> &Position {
+        // There is always at least one position.
+        self.positions.last().unwrap()
+    }
+
+    /// Plays a move at the end of this line.
+    ///
+    /// `variations` are alternatives to the move, starting from the current last position.
+    pub fn push(&mut self, m: LegalMove, variations: Vec<Line>) {
+        self.sans.push(m.san());
+        self.movements.push(m.movement);
+        self.positions.push(m.position);
+        self.check_marks.push(None);
+        self.nags.push(Vec::new());
+        self.comments.push(Vec::new());
+        self.variations.push(variations);
+    }
+
+    /// Attaches annotations to the last move of this line.
+    ///
+    /// Does nothing if the line has no moves.
+    pub fn annotate(&mut self, check_mark: Option<Mark>, nags: impl IntoIterator<Item = Nag>) {
+        if let Some(last) = self.check_marks.last_mut() {
+            *last = check_mark;
+        }
+        if let Some(last) = self.nags.last_mut() {
+            last.extend(nags);
+        }
+    }
+
+    /// Attaches comments to the position at the end of this line.
+    pub fn add_comments(&mut self, comments: impl IntoIterator<Item = Comment>) {
+        // There is always at least one position, and as many comment vectors as positions.
+        self.comments.last_mut().unwrap().extend(comments)
+    }
+
+    /// Returns how the game ended at the end of this line, if it did.
+    pub fn termination(&self) -> Option<Termination> {
+        termination(&self.previous_positions, &self.positions)
+    }
+
+    /// Writes the movetext of this line in PGN export format, as a sequence of tokens.
+    ///
+    /// Comments are split into words, so that they can be wrapped. Comments containing a closing
+    /// brace are written as rest of line comments instead, in a single token (see
+    /// [`is_rest_of_line_comment`]). Move number indications are part of the token of the move
+    /// they precede, so that they are never separated from it. Variations are enclosed in
+    /// parentheses, which are attached to their first and last tokens.
+    pub fn pgn_tokens(&self) -> crate::Result<Vec<String>> {
+        let mut tokens = Vec::new();
+        push_comment_tokens(&mut tokens, &self.comments[0])?;
+        let first_ply = self.positions[0].ply();
+        // Black moves need a move number indication at the beginning of the line, and after
+        // comments and variations (see section 8.2.2.2 of the specification).
+        let mut needs_number = true;
+        for (i, san) in self.sans.iter().enumerate() {
+            let ply = first_ply + i;
+            if self.positions[i].active == Color::White {
+                tokens.push(format!("{}. {san}", ply / 2 + 1));
+            } else if needs_number {
+                tokens.push(format!("{}... {san}", ply / 2 + 1));
+            } else {
+                tokens.push(san.to_string());
+            }
+            tokens.extend(self.nags[i].iter().map(|nag| format!("${}", nag.0)));
+            push_comment_tokens(&mut tokens, &self.comments[i + 1])?;
+            needs_number = !self.comments[i + 1].is_empty();
+            for variation in &self.variations[i] {
+                let mut variation = variation.pgn_tokens()?;
+                // Nothing can follow a rest of line comment on its line.
+                match variation.first_mut() {
+                    Some(first) if !is_rest_of_line_comment(first) => first.insert(0, '('),
+                    _ => variation.insert(0, "(".into()),
+                }
+                match variation.last_mut() {
+                    Some(last) if !is_rest_of_line_comment(last) => last.push(')'),
+                    _ => variation.push(")".into()),
+                }
+                tokens.extend(variation);
+                needs_number = true;
+            }
+        }
+        Ok(tokens)
+    }
+
+    /// Returns, for each position, the last value given by its comments.
+    fn comment_series<T>(&self, f: impl Fn(&Comment) -> Option<T>) -> Vec<Option<T>> {
+        self.comments
+            .iter()
+            .map(|comments| comments.iter().filter_map(&f).last())
+            .collect()
+    }
+
+    /// Computes the time each move took, in seconds.
+    ///
+    /// This is the time given by an `[%emt]` command following the move if there is one. Otherwise,
+    /// it is computed from the remaining times given by `[%clk]` commands before and after the move,
+    /// taking the time control into account. If neither is possible, the time is `None`.
+    pub fn time_spent(&self, time_control: Option<&TimeControl>) -> Vec<Option<f64>> {
+        let first_ply = self.positions[0].ply();
+        let clocks = self.comment_series(|comment| comment.clock);
+        let elapsed_times = self.comment_series(|comment| comment.elapsed_time);
+        (0..self.movements.len())
+            .map(|i| {
+                if let Some(elapsed_time) = elapsed_times[i + 1] {
+                    return Some(elapsed_time);
+                }
+                let time_control = time_control?;
+                let after = clocks[i + 1]?;
+                // The clock of the player before the move is the one after their previous move.
+                let before = match i {
+                    2.. => clocks[i - 1]?,
+                    _ if first_ply == 0 => time_control.initial_time().into(),
+                    _ => None?,
+                };
+                let move_number = ((first_ply + i) / 2 + 1) as u32;
+                let spent = before + f64::from(time_control.time_added(move_number)) - after;
+                // Clocks are usually given with a precision of at most a millisecond.
+                Some((spent * 1000.0).round() / 1000.0)
+            })
+            .collect()
+    }
+
+    pub fn to_json(&self) -> Json {
+        let evaluations = self.comment_series(|comment| comment.evaluation);
+        Json::object([
+            (
+                "positions",
+                Json::array(self.positions.iter().cloned().map(fen)),
+            ),
+            (
+                "moves",
+                Json::array(
+                    self.movements
+                        .iter()
+                        .map(|m| Json::array([m.from().to_string(), m.to().to_string()])),
+                ),
+            ),
+            (
+                "san",
+                Json::array(self.sans.iter().map(ToString::to_string)),
+            ),
+            (
+                "check-marks",
+                Json::array(self.check_marks.iter().map(|mark| mark.map(|m| m.name()))),
+            ),
+            (
+                "nags",
+                Json::array(
+                    self.nags
+                        .iter()
+                        .map(|nags| Json::array(nags.iter().copied().map(Nag::to_json))),
+                ),
+            ),
+            (
+                "comments",
+                Json::array(self.comments.iter().map(|comments| {
+                    Json::array(
+                        comments
+                            .iter()
+                            .filter(|comment| !comment.text.is_empty())
+                            .map(|comment| comment.text.as_str()),
+                    )
+                })),
+            ),
+            (
+                "marked-squares",
+                Json::array(self.comments.iter().map(|comments| {
+                    Json::array(
+                        comments
+                            .iter()
+                            .flat_map(|comment| comment.marked_squares.iter().copied())
+                            .map(MarkedSquare::to_json),
+                    )
+                })),
+            ),
+            (
+                "arrows",
+                Json::array(self.comments.iter().map(|comments| {
+                    Json::array(
+                        comments
+                            .iter()
+                            .flat_map(|comment| comment.arrows.iter().copied())
+                            .map(Arrow::to_json),
+                    )
+                })),
+            ),
+            (
+                "clocks",
+                Json::array(self.comment_series(|comment| comment.clock)),
+            ),
+            (
+                "elapsed-times",
+                Json::array(self.comment_series(|comment| comment.elapsed_time)),
+            ),
+            (
+                "evaluations",
+                Json::array(evaluations.iter().map(|evaluation| match evaluation {
+                    Some(Evaluation::Pawns(pawns)) => Some(*pawns),
+                    _ => None,
+                })),
+            ),
+            (
+                "mates",
+                Json::array(evaluations.iter().map(|evaluation| match evaluation {
+                    Some(Evaluation::Mate(moves)) => Some(*moves),
+                    _ => None,
+                })),
+            ),
+            ("termination", self.termination().map(|t| t.name()).into()),
+            (
+                "variations",
+                Json::array(
+                    self.variations
+                        .iter()
+                        .map(|variations| Json::array(variations.iter().map(Line::to_json))),
+                ),
+            ),
+        ])
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt;
+use std::fmt::{Display, Formatter, Write};
+
+/// A JSON value.
+///
+/// This is used to pass structured data to Typst, which can decode JSON natively.
+#[derive(Debug, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    /// A floating-point number. Non-finite numbers are serialized as `null`.
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// An object. The order of the entries is preserved.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Creates an object from `(key, value)` pairs.
+    pub fn object<const N: usize>(entries: [(&str, Json); N]) -> Self {
+        Self::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// Creates an array by converting each element of an iterator.
+    pub fn array<T: Into<Json>>(elements: impl IntoIterator<Item = T>) -> Self {
+        Self::Array(elements.into_iter().map(Into::into).collect())
+    }
+
+    /// Adds an entry at the end of an object.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self` is not an object.
+    pub fn push_entry(&mut self, key: &str, value: Json) {
+        let Self::Object(entries) = self else {
+            panic!("JSON value should be an object")
+        };
+        entries.push((key.to_string(), value));
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(value: u32) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+impl From<u64> for Json {
+    fn from(value: u64) -> Self {
+        Self::Integer(value as i64)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Self::Integer(value as i64)
+    }
+}
+
+impl From<i32> for Json {
+    fn from(value: i32) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(value: Vec<T>) -> Self {
+        Self::array(value)
+    }
+}
+
+fn write_string(f: &mut Formatter, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Null => f.write_str("null"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Integer(n) => write!(f, "{n}"),
+            Self::Float(x) if x.is_finite() => write!(f, "{x}"),
+            Self::Float(_) => f.write_str("null"),
+            Self::String(s) => write_string(f, s),
+            Self::Array(elements) => {
+                f.write_char('[')?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i != 0 {
+                        f.write_char(',')?
+                    }
+                    write!(f, "{element}")?
+                }
+                f.write_char(']')
+            }
+            Self::Object(entries) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i != 0 {
+                        f.write_char(',')?
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
@@ -1,82 +1,409 @@
 #![allow(refining_impl_trait)]
 
+mod bitboard;
+mod comment;
+mod error;
+mod export;
 mod fen;
+mod game;
+mod json;
 mod model;
+mod nag;
 mod pgn;
 mod san;
+mod tags;
+mod termination;
 mod utils;
+mod zobrist;
 
-use crate::fen::{fen, parse_fen};
-use crate::model::{Movement, Position};
-use crate::pgn::PgnGame;
+use crate::error::Error;
+use crate::export::export;
+use crate::fen::{
+    CastlingNotation, Validation, canonicalize, fen, fen_with, parse_fen, parse_fen_with,
+};
+use crate::game::Line;
+use crate::json::Json;
+use crate::model::{Color, Position, Square};
+use crate::nag::Nag;
+use crate::pgn::{GAME_TERMINATION_MARKERS, PgnGame, parse_games};
 use crate::san::parse_turn;
+use crate::tags::Tags;
 use crate::utils::SliceExt;
+use crate::zobrist::{is_same_position, zobrist_hash};
 use std::iter;
 use wasm_minimal_protocol::{initiate_protocol, wasm_func};
 
 initiate_protocol!();
 
-pub type Result<T> = std::result::Result<T, String>;
+pub type Result<T> = std::result::Result<T, Error>;
 
-fn serialize_game(
-    positions: impl IntoIterator<Item = Position>,
-    movements: impl IntoIterator<Item = Movement>,
-) -> Vec<u8> {
-    let serialized_positions = positions
+/// Serializes a sequence of strings by separating them with null bytes.
+fn serialize_strings(strings: impl IntoIterator<Item = String>) -> impl Iterator<Item = u8> {
+    strings
         .into_iter()
-        .flat_map(|position| iter::once(0).chain(fen(position).into_bytes()))
-        .skip(1);
-    let serialized_movements = movements
-        .into_iter()
-        .flat_map(|m| iter::once(0).chain(m.to_string().into_bytes()))
-        .skip(1);
-    serialized_positions
-        .chain(iter::once(0xff))
-        .chain(serialized_movements)
-        .collect()
+        .flat_map(|s| iter::once(0).chain(s.into_bytes()))
+        .skip(1)
 }
 
 #[wasm_func]
 pub fn invert_position(position: &[u8]) -> Result<Vec<u8>> {
-    let position =
-        std::str::from_utf8(position).map_err(|_| "internal error: FEN should be valid UTF-8")?;
-    Ok(fen(parse_fen(position)?.invert()).into_bytes())
+    let position = std::str::from_utf8(position)
+        .map_err(|_| Error::internal("internal error: FEN should be valid UTF-8"))?;
+    Ok(fen(parse_fen_with(position, Validation::Syntax)?.invert()).into_bytes())
+}
+
+/// Rewrites a position given in FEN using Shredder-FEN castling availabilities.
+#[wasm_func]
+pub fn shredder_fen(position: &[u8]) -> Result<Vec<u8>> {
+    let position = std::str::from_utf8(position)
+        .map_err(|_| Error::internal("internal error: FEN should be valid UTF-8"))?;
+    Ok(fen_with(
+        parse_fen_with(position, Validation::Syntax)?,
+        CastlingNotation::Shredder,
+    )
+    .into_bytes())
+}
+
+/// Canonicalizes a position given in FEN.
+///
+/// If `reset_move_counters` is not empty, the move counters are reset too. The result contains the
+/// canonical FEN and a description of each change.
+#[wasm_func]
+pub fn canonicalize_position(position: &[u8], reset_move_counters: &[u8]) -> Result<Vec<u8>> {
+    let position = std::str::from_utf8(position)
+        .map_err(|_| Error::internal("internal error: FEN should be valid UTF-8"))?;
+    let (position, changes) = canonicalize(&parse_fen(position)?, !reset_move_counters.is_empty());
+    Ok(Json::object([
+        ("fen", fen(position).into()),
+        ("changes", Json::array(changes)),
+    ])
+    .into_bytes())
 }
 
+/// Returns the Zobrist hash of a position given in FEN, as 16 hexadecimal digits.
 #[wasm_func]
-pub fn replay_game(starting_position: &[u8], turns: &[u8]) -> Result<Vec<u8>> {
+pub fn position_hash(position: &[u8]) -> Result<Vec<u8>> {
+    let position = std::str::from_utf8(position)
+        .map_err(|_| Error::internal("internal error: FEN should be valid UTF-8"))?;
+    Ok(format!("{:016x}", zobrist_hash(&parse_fen(position)?)).into_bytes())
+}
+
+/// Tests whether two positions given in FEN are the same, regardless of the move counters.
+#[wasm_func]
+pub fn same_position(position: &[u8], other: &[u8]) -> Result<Vec<u8>> {
+    let position = std::str::from_utf8(position)
+        .map_err(|_| Error::internal("internal error: FEN should be valid UTF-8"))?;
+    let other = std::str::from_utf8(other)
+        .map_err(|_| Error::internal("internal error: FEN should be valid UTF-8"))?;
+    let same = is_same_position(&parse_fen(position)?, &parse_fen(other)?);
+    Ok(Json::Bool(same).into_bytes())
+}
+
+#[wasm_func]
+pub fn legal_moves(position: &[u8], departure: &[u8]) -> Result<Vec<u8>> {
+    let position = std::str::from_utf8(position)
+        .map_err(|_| Error::internal("internal error: FEN should be valid UTF-8"))?;
+    let departure = std::str::from_utf8(departure)
+        .map_err(|_| Error::internal("internal error: square should be valid UTF-8"))?;
+    let departure = match departure {
+        "" => None,
+        square => Some(square.parse::<Square>()?),
+    };
+    let moves = san::legal_moves(&parse_fen(position)?);
+    Ok(serialize_strings(
+        moves
+            .iter()
+            .filter(|m| departure.is_none_or(|square| m.movement.from() == square))
+            .map(|m| format!("{} {}", m.movement, m.san())),
+    )
+    .collect())
+}
+
+/// Returns, for each square of a position given in FEN, the squares of the white and black pieces
+/// that attack it.
+///
+/// If `x_ray` is not empty, x-ray attacks are included (see
+/// [`Board::attackers`](crate::model::Board::attackers)).
+#[wasm_func]
+pub fn attack_map(position: &[u8], x_ray: &[u8]) -> Result<Vec<u8>> {
+    let position = std::str::from_utf8(position)
+        .map_err(|_| Error::internal("internal error: FEN should be valid UTF-8"))?;
+    let board = parse_fen_with(position, Validation::Syntax)?.board;
+    let attackers = |square, color| {
+        Json::array(
+            board
+                .attackers(square, color, !x_ray.is_empty())
+                .squares()
+                .map(|attacker| attacker.to_string()),
+        )
+    };
+    Ok(Json::Object(
+        Square::all()
+            .map(|square| {
+                let entry = Json::object([
+                    ("white", attackers(square, Color::White)),
+                    ("black", attackers(square, Color::Black)),
+                ]);
+                (square.to_string(), entry)
+            })
+            .collect(),
+    )
+    .into_bytes())
+}
+
+/// Counts the positions reached after exactly `depth` legal moves from a position.
+///
+/// If `divide` is not empty, the count for each legal move is given as well. The depth is at most
+/// [`san::MAX_PERFT_DEPTH`].
+#[wasm_func]
+pub fn perft(position: &[u8], depth: &[u8], divide: &[u8]) -> Result<Vec<u8>> {
+    let position = std::str::from_utf8(position)
+        .map_err(|_| Error::internal("internal error: FEN should be valid UTF-8"))?;
+    let depth = std::str::from_utf8(depth)
+        .ok()
+        .and_then(|depth| depth.parse().ok())
+        .ok_or_else(|| Error::internal("internal error: depth should be a valid integer"))?;
+    if depth > san::MAX_PERFT_DEPTH {
+        Err(format!(
+            "invalid depth: {depth} (the maximum is {})",
+            san::MAX_PERFT_DEPTH
+        ))?
+    }
+    let position = parse_fen(position)?;
+    if divide.is_empty() {
+        return Ok(Json::object([
+            ("nodes", san::perft(&position, depth).into()),
+            ("moves", Json::Null),
+        ])
+        .into_bytes());
+    }
+    let moves = san::divide(&position, depth);
+    // At depth 0, the only position reached is the initial one, and there is no move.
+    let nodes = match depth {
+        0 => 1,
+        _ => moves.iter().map(|(_, nodes)| nodes).sum::<u64>(),
+    };
+    Ok(Json::object([
+        ("nodes", nodes.into()),
+        (
+            "moves",
+            Json::array(moves.into_iter().map(|(m, nodes)| {
+                Json::object([
+                    ("from", m.movement.from().to_string().into()),
+                    ("to", m.movement.to().to_string().into()),
+                    ("san", m.san().to_string().into()),
+                    ("nodes", nodes.into()),
+                ])
+            })),
+        ),
+    ])
+    .into_bytes())
+}
+
+/// Deserializes a sequence of strings separated with null bytes.
+///
+/// An empty slice is deserialized as an empty sequence.
+fn deserialize_strings<'a>(bytes: &'a [u8], what: &str) -> Result<Vec<&'a str>> {
+    if bytes.is_empty() {
+        return Ok(Vec::new());
+    }
+    bytes
+        .split_on(0)
+        .map(|s| {
+            std::str::from_utf8(s).map_err(|_| {
+                Error::internal(format!("internal error: {what} should be valid UTF-8"))
+            })
+        })
+        .collect()
+}
+
+/// Deserializes tag pairs from a null-separated list alternating tag names and values.
+fn deserialize_tags(bytes: &[u8]) -> Result<Tags> {
+    let mut tags = Tags::default();
+    for pair in deserialize_strings(bytes, "tags")?.chunks(2) {
+        let [name, value] = pair else {
+            Err(Error::internal(
+                "internal error: each tag name should have a value",
+            ))?
+        };
+        tags.insert(name, value.to_string())?;
+    }
+    Ok(tags)
+}
+
+/// Applies null-separated turns to a starting position given in FEN.
+///
+/// Chess960 castling rules are used if `chess960` is true or if the castling availabilities of
+/// the starting position select them.
+fn replay_turns(starting_position: &[u8], turns: &[u8], chess960: bool) -> Result<Line> {
     let starting_position = std::str::from_utf8(starting_position)
-        .map_err(|_| "internal error: FEN should be valid UTF-8")?;
-    let turns = turns.split_on(0);
-    let mut positions = Vec::with_capacity(turns.size_hint().0 + 1);
-    let mut movements = Vec::with_capacity(turns.size_hint().0);
-    positions.push(parse_fen(starting_position)?);
-    for (i, turn) in turns.enumerate() {
+        .map_err(|_| Error::internal("internal error: FEN should be valid UTF-8"))?;
+    let starting_position = parse_fen(starting_position)?;
+    let mut line = Line::new(Position {
+        chess960: starting_position.chess960 || chess960,
+        ..starting_position
+    });
+    for turn in turns.split_on(0) {
         let Ok(turn) = std::str::from_utf8(turn) else {
-            Err("internal error: each turn should be a valid UTF-8 string")?
+            Err(Error::internal(
+                "internal error: each turn should be a valid UTF-8 string",
+            ))?
         };
-        let (position, movement) = parse_turn(turn)?.apply(i, positions.last().unwrap())?;
-        positions.push(position);
-        movements.push(movement);
+        let position = line.last_position();
+        let turn = parse_turn(turn)?;
+        let m = turn.apply(position.ply(), position)?;
+        line.push(m, Vec::new());
+        line.annotate(turn.mark(), turn.annotation().map(Nag::from));
     }
-    Ok(serialize_game(positions, movements))
+    Ok(line)
 }
 
+/// Applies turns to a starting position.
+///
+/// If `chess960` is not empty, Chess960 castling rules are used.
 #[wasm_func]
-pub fn game_from_pgn(pgn: &[u8]) -> Result<Vec<u8>> {
+pub fn replay_game(starting_position: &[u8], turns: &[u8], chess960: &[u8]) -> Result<Vec<u8>> {
+    Ok(
+        replay_turns(starting_position, turns, !chess960.is_empty())?
+            .to_json()
+            .into_bytes(),
+    )
+}
+
+/// Writes the game obtained by applying turns to a starting position in PGN export format.
+///
+/// `tags` is a null-separated list alternating tag names and values. Unless a `Result` tag is
+/// given, the result is deduced from the final position. Chess960 castling rules are used if the
+/// `Variant` tag says so.
+#[wasm_func]
+pub fn export_game(starting_position: &[u8], turns: &[u8], tags: &[u8]) -> Result<Vec<u8>> {
+    let mut tags = deserialize_tags(tags)?;
+    let line = replay_turns(starting_position, turns, tags.is_chess960())?;
+    let starting_position = fen(line.first_position().clone());
+    if starting_position != fen(Position::default()) && tags.get("FEN").is_none() {
+        tags.insert("SetUp", "1".into())?;
+        tags.insert("FEN", starting_position)?;
+    }
+    let result = match tags.get("Result") {
+        Some(result) if GAME_TERMINATION_MARKERS.contains(&result) => result,
+        Some(result) => Err(format!("invalid value for tag Result: {result:?}"))?,
+        None => line
+            .termination()
+            .and_then(|termination| termination.result(line.last_position()))
+            .unwrap_or("*"),
+    };
+    Ok(export(&tags, &line, result)?.into_bytes())
+}
+
+/// Parses a PGN game.
+///
+/// If `strict` is not empty, the result also contains the warnings found when validating the game.
+#[wasm_func]
+pub fn game_from_pgn(pgn: &[u8], strict: &[u8]) -> Result<Vec<u8>> {
     let Ok(pgn) = std::str::from_utf8(pgn) else {
         // The specification actually requires that PGN uses ASCII, but we allow UTF-8 because this
         // is today's world standard.
-        Err("internal error: PGN should be a valid UTF-8 string")?
+        Err(Error::internal(
+            "internal error: PGN should be a valid UTF-8 string",
+        ))?
+    };
+    let game = pgn.parse::<PgnGame>()?;
+    let json = game
+        .to_json(!strict.is_empty())
+        .map_err(|error| error.with_source(pgn))?;
+    Ok(json.into_bytes())
+}
+
+/// Rewrites a PGN game in PGN export format.
+#[wasm_func]
+pub fn normalize_pgn(pgn: &[u8]) -> Result<Vec<u8>> {
+    let Ok(pgn) = std::str::from_utf8(pgn) else {
+        Err(Error::internal(
+            "internal error: PGN should be a valid UTF-8 string",
+        ))?
     };
     let game = pgn.parse::<PgnGame>()?;
-    let mut positions = Vec::with_capacity(game.len() + 1);
-    let mut movements = Vec::with_capacity(game.len());
-    positions.push(game.starting_position);
-    for (i, turn) in game.turns.iter().enumerate() {
-        let (position, movement) = turn.apply(i, positions.last().unwrap())?;
-        positions.push(position);
-        movements.push(movement);
+    let (line, _) = game.replay().map_err(|error| error.with_source(pgn))?;
+    Ok(export(&game.tags, &line, game.result)?.into_bytes())
+}
+
+/// Parses PGN data containing any number of games.
+///
+/// `indices` is a null-separated list of the indices of the games to return. If it is empty, all
+/// games are returned.
+///
+/// `tags` is a null-separated list alternating tag names and values. Only the games matching each
+/// tag name are returned. A tag name can appear several times, in which case any of its values
+/// matches. `player`, if not empty, only returns the games in which the `White` or `Black` tag has
+/// this value.
+#[wasm_func]
+pub fn games_from_pgn(pgn: &[u8], indices: &[u8], tags: &[u8], player: &[u8]) -> Result<Vec<u8>> {
+    let Ok(pgn) = std::str::from_utf8(pgn) else {
+        Err(Error::internal(
+            "internal error: PGN should be a valid UTF-8 string",
+        ))?
+    };
+    let indices = match indices {
+        [] => None,
+        indices => Some(
+            indices
+                .split_on(0)
+                .map(|index| {
+                    std::str::from_utf8(index)
+                        .ok()
+                        .and_then(|index| index.parse().ok())
+                        .ok_or_else(|| {
+                            Error::internal("internal error: indices should be valid integers")
+                        })
+                })
+                .collect::<std::result::Result<Vec<usize>, _>>()?,
+        ),
+    };
+    let tags = deserialize_strings(tags, "tags")?;
+    let Ok(player) = std::str::from_utf8(player) else {
+        Err(Error::internal(
+            "internal error: player should be a valid UTF-8 string",
+        ))?
+    };
+
+    let mut criteria = Vec::<(Vec<&str>, Vec<&str>)>::new();
+    for pair in tags.chunks(2) {
+        let [name, value] = pair else {
+            Err(Error::internal(
+                "internal error: each tag name should have a value",
+            ))?
+        };
+        match criteria.iter_mut().find(|(names, _)| names == &[*name]) {
+            Some((_, values)) => values.push(value),
+            None => criteria.push((vec![name], vec![value])),
+        }
     }
-    Ok(serialize_game(positions, movements))
+    if !player.is_empty() {
+        criteria.push((vec!["White", "Black"], vec![player]));
+    }
+
+    let games = parse_games(pgn)
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| indices.as_ref().is_none_or(|indices| indices.contains(i)))
+        .filter(|(_, entry)| entry.tags.matches(&criteria))
+        .map(|(i, entry)| {
+            let game = entry
+                .game
+                .and_then(|game| game.to_json(false))
+                .map_err(|error| error.with_source(pgn));
+            let mut json = match game {
+                Ok(mut json) => {
+                    json.push_entry("error", Json::Null);
+                    json
+                }
+                Err(error) => Json::object([
+                    ("tags", entry.tags.to_json()),
+                    ("error", error.to_string().into()),
+                ]),
+            };
+            json.push_entry("index", i.into());
+            json
+        });
+    Ok(Json::array(games).into_bytes())
 }
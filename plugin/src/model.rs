@@ -1,7 +1,11 @@
+use crate::bitboard::{
+    Bitboard, bishop_attacks, king_attacks, knight_attacks, pawn_attacks, rook_attacks,
+};
+use crate::error::Error;
 use crate::utils::{CharExt, Finite, FromChar, Name, cartesian_product};
+use crate::zobrist::{compute_hash, piece_key, rehash};
 use std::fmt;
 use std::fmt::{Debug, Display, Formatter};
-use std::ops::{Index, IndexMut};
 use std::str::FromStr;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -89,7 +93,7 @@ impl Name for File {
 }
 
 impl FromChar for File {
-    type Err = String;
+    type Err = Error;
 
     fn from_char(c: char) -> crate::Result<Self> {
         match c {
@@ -151,19 +155,6 @@ impl Rank {
             Self::Eight => 7,
         }
     }
-
-    pub fn mirror(self) -> Self {
-        match self {
-            Self::One => Self::Eight,
-            Self::Two => Self::Seven,
-            Self::Three => Self::Six,
-            Self::Four => Self::Five,
-            Self::Five => Self::Four,
-            Self::Six => Self::Three,
-            Self::Seven => Self::Two,
-            Self::Eight => Self::One,
-        }
-    }
 }
 
 impl Finite for Rank {
@@ -197,7 +188,7 @@ impl Name for Rank {
 }
 
 impl FromChar for Rank {
-    type Err = String;
+    type Err = Error;
 
     fn from_char(c: char) -> crate::Result<Self> {
         match c {
@@ -244,12 +235,14 @@ impl Square {
         self.rank
     }
 
-    /// Returns the coordinate of this square from the other player's point of view.
-    pub fn transpose(self) -> Self {
-        Self {
-            file: self.file.mirror(),
-            rank: self.rank.mirror(),
-        }
+    /// Returns the index of this square, from 0 for a1 to 63 for h8, going through the files of
+    /// each rank first.
+    pub fn index(self) -> usize {
+        8 * self.rank.index() + self.file.index()
+    }
+
+    pub fn from_index(index: usize) -> Option<Self> {
+        Some(Self::new(File::new(index % 8)?, Rank::new(index / 8)?))
     }
 }
 
@@ -266,7 +259,7 @@ impl Name for Square {
 }
 
 impl FromStr for Square {
-    type Err = String;
+    type Err = Error;
 
     fn from_str(s: &str) -> crate::Result<Self> {
         let [f, r] = s.chars().collect::<Vec<_>>()[..] else {
@@ -298,6 +291,14 @@ impl Movement {
     pub fn new(from: Square, to: Square) -> Self {
         Self { from, to }
     }
+
+    pub fn from(self) -> Square {
+        self.from
+    }
+
+    pub fn to(self) -> Square {
+        self.to
+    }
 }
 
 impl Debug for Movement {
@@ -326,6 +327,21 @@ impl Color {
         }
     }
 
+    pub fn index(self) -> usize {
+        match self {
+            Self::White => 0,
+            Self::Black => 1,
+        }
+    }
+
+    /// Returns the rank on which the pieces of this color start the game.
+    pub fn home_rank(self) -> Rank {
+        match self {
+            Self::White => Rank::One,
+            Self::Black => Rank::Eight,
+        }
+    }
+
     /// Returns the rank pawns of this color have to target to capture en passant.
     pub fn en_passant_target_rank(self) -> Rank {
         match self {
@@ -341,6 +357,14 @@ impl Color {
             Self::Black => Rank::Four,
         }
     }
+
+    /// Returns the rank on which pawns of this color are promoted.
+    pub fn promotion_rank(self) -> Rank {
+        match self {
+            Self::White => Rank::Eight,
+            Self::Black => Rank::One,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
@@ -354,6 +378,19 @@ pub enum PieceKind {
     King,
 }
 
+impl PieceKind {
+    pub fn index(self) -> usize {
+        match self {
+            Self::Pawn => 0,
+            Self::Knight => 1,
+            Self::Bishop => 2,
+            Self::Rook => 3,
+            Self::Queen => 4,
+            Self::King => 5,
+        }
+    }
+}
+
 impl Finite for PieceKind {
     fn values() -> impl IntoIterator<Item = Self> {
         [
@@ -368,7 +405,7 @@ impl Finite for PieceKind {
 }
 
 impl FromChar for PieceKind {
-    type Err = String;
+    type Err = Error;
 
     fn from_char(c: char) -> crate::Result<Self> {
         match c {
@@ -406,17 +443,9 @@ impl Piece {
     pub fn new(color: Color, kind: PieceKind) -> Self {
         Self { color, kind }
     }
-
-    /// Flips the color of this piece.
-    pub fn flip(self) -> Self {
-        Self {
-            color: self.color.flip(),
-            kind: self.kind,
-        }
-    }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum SquareContent {
     Empty,
     Piece(Piece),
@@ -437,73 +466,183 @@ impl SquareContent {
             Self::Piece(p) => p == piece,
         }
     }
+}
 
-    pub fn map(self, f: impl FnOnce(Piece) -> Piece) -> Self {
-        match self {
-            Self::Empty => Self::Empty,
-            Self::Piece(p) => Self::Piece(f(p)),
+/// A chessboard, stored as bitboards: one for the pieces of each color, and one for the pieces of
+/// each kind.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Board {
+    colors: [Bitboard; 2],
+    kinds: [Bitboard; 6],
+    /// The Zobrist hash of the pieces, updated as they are added and removed (see
+    /// [`zobrist_hash`](crate::zobrist::zobrist_hash)).
+    zobrist: u64,
+}
+
+impl Board {
+    /// Creates a board from its squares, given rank by rank, from the first to the eighth.
+    pub fn new(squares: [[SquareContent; 8]; 8]) -> Self {
+        let mut board = Self {
+            colors: [Bitboard::EMPTY; 2],
+            kinds: [Bitboard::EMPTY; 6],
+            zobrist: 0,
+        };
+        for square in Square::all() {
+            board.set(square, squares[square.rank.index()][square.file.index()])
         }
+        board
     }
 
-    pub fn flip(self) -> Self {
-        self.map(Piece::flip)
+    /// Creates a board from its bitboards, computing its hash.
+    fn from_bitboards(colors: [Bitboard; 2], kinds: [Bitboard; 6]) -> Self {
+        let mut board = Self {
+            colors,
+            kinds,
+            zobrist: 0,
+        };
+        for square in board.occupied().squares() {
+            if let SquareContent::Piece(piece) = board.get(square) {
+                board.zobrist ^= piece_key(piece, square)
+            }
+        }
+        board
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct Board<const WIDTH: usize = 8, const HEIGHT: usize = 8> {
-    /// The squares of the board, in file-major order.
-    squares: [[SquareContent; WIDTH]; HEIGHT],
-}
+    pub fn get(&self, square: Square) -> SquareContent {
+        let Some(color) = [Color::White, Color::Black]
+            .into_iter()
+            .find(|color| self.colors[color.index()].contains(square))
+        else {
+            return SquareContent::Empty;
+        };
+        // A square occupied by a piece of some color is also occupied by a piece of some kind.
+        let kind = PieceKind::iter()
+            .find(|kind| self.kinds[kind.index()].contains(square))
+            .unwrap();
+        SquareContent::Piece(Piece::new(color, kind))
+    }
+
+    pub fn set(&mut self, square: Square, content: SquareContent) {
+        if let SquareContent::Piece(piece) = self.get(square) {
+            self.toggle(square, piece)
+        }
+        if let SquareContent::Piece(piece) = content {
+            self.toggle(square, piece)
+        }
+    }
+
+    /// Adds a piece on a square, or removes it if it is already there.
+    fn toggle(&mut self, square: Square, piece: Piece) {
+        let bit = Bitboard::from_square(square);
+        self.colors[piece.color.index()] ^= bit;
+        self.kinds[piece.kind.index()] ^= bit;
+        self.zobrist ^= piece_key(piece, square);
+    }
 
-impl<const WIDTH: usize, const HEIGHT: usize> Board<WIDTH, HEIGHT> {
-    pub fn new(squares: [[SquareContent; WIDTH]; HEIGHT]) -> Self {
-        Self { squares }
+    /// Returns the Zobrist hash of the pieces on this board.
+    pub fn zobrist(&self) -> u64 {
+        self.zobrist
+    }
+
+    /// Returns the squares occupied by the pieces of a player.
+    pub fn by_color(&self, color: Color) -> Bitboard {
+        self.colors[color.index()]
+    }
+
+    /// Returns the squares occupied by a specific piece.
+    pub fn pieces(&self, piece: Piece) -> Bitboard {
+        self.colors[piece.color.index()] & self.kinds[piece.kind.index()]
+    }
+
+    /// Returns the occupied squares.
+    pub fn occupied(&self) -> Bitboard {
+        self.colors[0] | self.colors[1]
     }
 
     /// Mirrors the board vertically (i.e., along a horizontal axis).
     pub fn mirror(&self) -> Self {
-        let mut squares = self.squares;
-        squares.reverse();
-        Self { squares }
+        Self::from_bitboards(
+            self.colors.map(Bitboard::mirror),
+            self.kinds.map(Bitboard::mirror),
+        )
     }
 
     /// Flips the colors of the pieces.
     pub fn flip(&self) -> Self {
-        Self {
-            squares: self.squares.map(|r| r.map(SquareContent::flip)),
-        }
+        let [white, black] = self.colors;
+        Self::from_bitboards([black, white], self.kinds)
+    }
+
+    /// Returns the file of the king of a player, if it is on their first rank.
+    pub fn home_king_file(&self, color: Color) -> Option<File> {
+        let king = Piece::new(color, PieceKind::King);
+        (self.pieces(king) & Bitboard::rank(color.home_rank()))
+            .first()
+            .map(Square::file)
+    }
+
+    /// Returns the pieces of a player that attack a square, whether it is empty or occupied by a
+    /// piece of either player.
+    ///
+    /// If `x_ray` is `true`, sliding pieces also attack through the pieces of the same player that
+    /// slide in the same direction, such as a rook behind a queen on the same file.
+    pub fn attackers(&self, square: Square, color: Color, x_ray: bool) -> Bitboard {
+        let occupied = self.occupied();
+        let pieces = |kind| self.pieces(Piece::new(color, kind));
+        let queens = pieces(PieceKind::Queen);
+        let diagonal_sliders = pieces(PieceKind::Bishop) | queens;
+        let orthogonal_sliders = pieces(PieceKind::Rook) | queens;
+        let (diagonal_blockers, orthogonal_blockers) = match x_ray {
+            true => (occupied & !diagonal_sliders, occupied & !orthogonal_sliders),
+            false => (occupied, occupied),
+        };
+        // A piece attacks a square if a piece of the same kind on that square would attack it, or
+        // if a pawn of the other color would for pawns.
+        (pawn_attacks(color.flip(), square) & pieces(PieceKind::Pawn))
+            | (knight_attacks(square) & pieces(PieceKind::Knight))
+            | (bishop_attacks(square, diagonal_blockers) & diagonal_sliders)
+            | (rook_attacks(square, orthogonal_blockers) & orthogonal_sliders)
+            | (king_attacks(square) & pieces(PieceKind::King))
     }
-}
-
-impl Index<Square> for Board {
-    type Output = SquareContent;
 
-    fn index(&self, index: Square) -> &Self::Output {
-        &self.squares[index.rank.index()][index.file.index()]
+    /// Tests whether a square is attacked by a piece of a player.
+    pub fn is_attacked(&self, square: Square, color: Color) -> bool {
+        !self.attackers(square, color, false).is_empty()
     }
 }
 
-impl IndexMut<Square> for Board {
-    fn index_mut(&mut self, index: Square) -> &mut Self::Output {
-        &mut self.squares[index.rank.index()][index.file.index()]
-    }
+/// A side of the board, on which a player can castle.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Side {
+    King,
+    Queen,
 }
 
-#[derive(Debug, Copy, Clone)]
+/// The ability of each player to castle on each side.
+///
+/// Each availability is given by the file of the rook the player can castle with, so that
+/// positions where the rooks do not start in the corners can be represented.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct CastlingAvailabilities {
-    pub white_kingside: bool,
-    pub white_queenside: bool,
-    pub black_kingside: bool,
-    pub black_queenside: bool,
+    pub white_kingside: Option<File>,
+    pub white_queenside: Option<File>,
+    pub black_kingside: Option<File>,
+    pub black_queenside: Option<File>,
 }
 
 impl CastlingAvailabilities {
+    pub const NONE: Self = Self {
+        white_kingside: None,
+        white_queenside: None,
+        black_kingside: None,
+        black_queenside: None,
+    };
+
     pub const ALL: Self = Self {
-        white_kingside: true,
-        white_queenside: true,
-        black_kingside: true,
-        black_queenside: true,
+        white_kingside: Some(File::H),
+        white_queenside: Some(File::A),
+        black_kingside: Some(File::H),
+        black_queenside: Some(File::A),
     };
 
     pub fn flip(self) -> Self {
@@ -515,30 +654,35 @@ impl CastlingAvailabilities {
         }
     }
 
-    pub fn kingside_for(self, color: Color) -> bool {
-        match color {
-            Color::White => self.white_kingside,
-            Color::Black => self.black_kingside,
+    /// Returns the file of the rook a player can castle with on a side, if they can.
+    pub fn get(self, color: Color, side: Side) -> Option<File> {
+        match (color, side) {
+            (Color::White, Side::King) => self.white_kingside,
+            (Color::White, Side::Queen) => self.white_queenside,
+            (Color::Black, Side::King) => self.black_kingside,
+            (Color::Black, Side::Queen) => self.black_queenside,
         }
     }
 
-    pub fn queenside_for(self, color: Color) -> bool {
-        match color {
-            Color::White => self.white_queenside,
-            Color::Black => self.black_queenside,
+    pub fn get_mut(&mut self, color: Color, side: Side) -> &mut Option<File> {
+        match (color, side) {
+            (Color::White, Side::King) => &mut self.white_kingside,
+            (Color::White, Side::Queen) => &mut self.white_queenside,
+            (Color::Black, Side::King) => &mut self.black_kingside,
+            (Color::Black, Side::Queen) => &mut self.black_queenside,
         }
     }
 
     pub fn remove_for(self, color: Color) -> Self {
         match color {
             Color::White => Self {
-                white_kingside: false,
-                white_queenside: false,
+                white_kingside: None,
+                white_queenside: None,
                 ..self
             },
             Color::Black => Self {
-                black_kingside: false,
-                black_queenside: false,
+                black_kingside: None,
+                black_queenside: None,
                 ..self
             },
         }
@@ -554,42 +698,69 @@ pub struct Position {
     pub en_passant_target_file: Option<File>,
     pub halfmove: u32,
     pub fullmove: u32,
+    /// Whether castling follows the rules of Chess960, where the king and the rooks can start on
+    /// any file.
+    pub chess960: bool,
+    /// The Zobrist hash of this position (see [`crate::zobrist::zobrist_hash`]).
+    ///
+    /// It is updated as moves are made, and must be recomputed with [`rehash`] when other fields
+    /// are modified directly.
+    pub zobrist: u64,
 }
 
 impl Position {
     pub fn default_with_board(board: Board) -> Self {
-        Self {
+        let mut position = Self {
             board,
             active: Color::White,
             castling_availabilities: CastlingAvailabilities::ALL,
             en_passant_target_file: None,
             halfmove: 0,
             fullmove: 1,
-        }
+            chess960: false,
+            zobrist: 0,
+        };
+        position.zobrist = compute_hash(&position, None);
+        position
     }
 
     pub fn at(&self, square: Square) -> SquareContent {
-        self.board[square]
+        self.board.get(square)
     }
 
     pub fn next_fullmove(&self) -> u32 {
         if self.active == Color::Black {
-            self.fullmove + 1
+            self.fullmove.saturating_add(1)
         } else {
             self.fullmove
         }
     }
 
+    /// Returns the index of the next halfmove in the game, according to the fullmove number.
+    ///
+    /// The first move of White has index 0, the first move of Black has index 1, etc.
+    pub fn ply(&self) -> usize {
+        let ply = (self.fullmove.saturating_sub(1) as usize).saturating_mul(2);
+        match self.active {
+            Color::White => ply,
+            Color::Black => ply.saturating_add(1),
+        }
+    }
+
     /// Inverts this position, mirroring it, and flipping the piece colors.
     pub fn invert(&self) -> Self {
-        Self {
+        let mut inverted = Self {
             board: self.board.mirror().flip(),
             active: self.active.flip(),
             castling_availabilities: self.castling_availabilities.flip(),
             en_passant_target_file: self.en_passant_target_file.map(File::mirror),
             halfmove: self.halfmove,
             fullmove: self.fullmove,
-        }
+            chess960: self.chess960,
+            zobrist: 0,
+        };
+        rehash(&mut inverted);
+        inverted
     }
 }
 
@@ -610,17 +781,15 @@ impl Default for Position {
         let q = SquareContent::Piece(Piece::new(Color::Black, PieceKind::Queen));
         let k = SquareContent::Piece(Piece::new(Color::Black, PieceKind::King));
 
-        Self::default_with_board(Board {
-            squares: [
-                [R, N, B, Q, K, B, N, R],
-                [P, P, P, P, P, P, P, P],
-                [SquareContent::Empty; 8],
-                [SquareContent::Empty; 8],
-                [SquareContent::Empty; 8],
-                [SquareContent::Empty; 8],
-                [p, p, p, p, p, p, p, p],
-                [r, n, b, q, k, b, n, r],
-            ],
-        })
+        Self::default_with_board(Board::new([
+            [R, N, B, Q, K, B, N, R],
+            [P, P, P, P, P, P, P, P],
+            [SquareContent::Empty; 8],
+            [SquareContent::Empty; 8],
+            [SquareContent::Empty; 8],
+            [SquareContent::Empty; 8],
+            [p, p, p, p, p, p, p, p],
+            [r, n, b, q, k, b, n, r],
+        ]))
     }
 }
@@ -1,20 +1,59 @@
+use crate::comment::Comment;
+use crate::error::{Error, ErrorKind};
+use crate::export::SEVEN_TAG_ROSTER;
 use crate::fen::parse_fen;
+use crate::game::Line;
+use crate::json::Json;
 use crate::model::Position;
-use crate::san::AnnotatedAlgebraicTurn;
-use std::collections::HashMap;
+use crate::nag::Nag;
+use crate::san::{AnnotatedAlgebraicTurn, LegalMove, Mark};
+use crate::tags::{Tags, TimeControl};
+use crate::utils::Name;
 use std::str::FromStr;
 
+/// The game termination markers: White wins, Black wins, drawn game, and unknown result.
+pub const GAME_TERMINATION_MARKERS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// The maximum nesting depth of recursive annotation variations.
+///
+/// Variations are parsed, replayed and serialized recursively, so this prevents deeply nested
+/// variations from overflowing the stack.
+const MAX_VARIATION_DEPTH: usize = 64;
+
 /// A type that can be used to parse Portable Game Notation (PGN).
 ///
 /// The specification of PGN is available on the Internet Archive at
 /// <https://ia902908.us.archive.org/26/items/pgn-standard-1994-03-12/PGN_standard_1994-03-12.txt>.
 struct PgnParser<'a> {
+    /// The whole string being parsed, used to locate errors.
+    source: &'a str,
+    /// The remaining content to parse.
     content: &'a str,
+    /// The comments that were skipped, but not yet attached to an element of the game.
+    pending_comments: Vec<Comment>,
 }
 
 impl<'a> PgnParser<'a> {
     fn new(content: &'a str) -> Self {
-        Self { content }
+        Self {
+            source: content,
+            content,
+            pending_comments: Vec::new(),
+        }
+    }
+
+    /// Returns the byte offset of the remaining content in the string being parsed.
+    fn offset(&self) -> usize {
+        self.source.len() - self.content.len()
+    }
+
+    /// Sets the kind and location of an error found at the current offset, unless they are
+    /// already known.
+    fn locate(&self, error: Error) -> Error {
+        error
+            .in_context(ErrorKind::Pgn)
+            .at(self.offset())
+            .with_source(self.source)
     }
 
     /// Returns a boolean indicating whether there are remaining characters to read.
@@ -53,21 +92,21 @@ impl<'a> PgnParser<'a> {
 
     /// Advances the parser until right after the first occurrence of the specified character.
     ///
-    /// If the specified character does not appear, `false` is returned, and the parser is left
-    /// unmodified. Otherwise, the iterator is advanced as described above, and `true` is returned.
-    fn eat_until(&mut self, c: char) -> bool {
-        match self.content.find(c) {
-            None => false,
-            Some(i) => {
-                self.content = &self.content[i + c.len_utf8()..];
-                true
-            }
-        }
+    /// If the specified character does not appear, `None` is returned, and the parser is left
+    /// unmodified. Otherwise, the iterator is advanced as described above, and the content that
+    /// was skipped (excluding the specified character) is returned, wrapped in `Some`.
+    fn read_until(&mut self, c: char) -> Option<&'a str> {
+        let i = self.content.find(c)?;
+        let skipped = &self.content[..i];
+        self.content = &self.content[i + c.len_utf8()..];
+        Some(skipped)
     }
 
     /// Tries to skip a comment.
     ///
-    /// Returns a boolean indicating whether a comment was skipped.
+    /// Returns a boolean indicating whether a comment was skipped. The comment is added to the
+    /// pending comments (see [`Self::take_comments`]), after extracting the commands it embeds (see
+    /// [`Comment`]).
     ///
     /// # Definition
     ///
@@ -86,19 +125,30 @@ impl<'a> PgnParser<'a> {
     ///
     /// [the specification]: https://ia902908.us.archive.org/26/items/pgn-standard-1994-03-12/PGN_standard_1994-03-12.txt
     fn eat_comment(&mut self) -> crate::Result<bool> {
-        Ok(if self.content.starts_with(';') {
-            if !self.eat_until('\n') {
-                self.content = ""
-            }
-            true
-        } else if self.content.starts_with('{') {
-            if !self.eat_until('}') {
-                Err("invalid PGN: unmatched opening curly brace")?
-            }
-            true
+        let start = self.offset();
+        let comment = if let Some(content) = self.content.strip_prefix(';') {
+            self.content = content;
+            self.read_until('\n').unwrap_or_else(|| {
+                let comment = self.content;
+                self.content = "";
+                comment
+            })
+        } else if let Some(content) = self.content.strip_prefix('{') {
+            self.content = content;
+            self.read_until('}').ok_or_else(|| {
+                Error::from("invalid PGN: unmatched opening curly brace").at(start)
+            })?
         } else {
-            false
-        })
+            return Ok(false);
+        };
+        self.pending_comments
+            .push(comment.parse().map_err(|error: Error| error.at(start))?);
+        Ok(true)
+    }
+
+    /// Returns the comments that were skipped since the last call to this function.
+    fn take_comments(&mut self) -> Vec<Comment> {
+        std::mem::take(&mut self.pending_comments)
     }
 
     /// Skips whitespace and comments.
@@ -230,19 +280,20 @@ impl<'a> PgnParser<'a> {
 
     /// Parses the tag pair section of a game.
     ///
-    /// Returns a `HashMap` containing each `(tag_name, tag_value)` pair.
+    /// Returns each `(tag_name, tag_value)` pair, in order.
     ///
     /// # Definition
     ///
     /// [The specification] defines the tag pair section in section 8.1.
     ///
     /// [the specification]: https://ia902908.us.archive.org/26/items/pgn-standard-1994-03-12/PGN_standard_1994-03-12.txt
-    fn parse_tag_pair_section<'b>(&'b mut self) -> crate::Result<HashMap<&'a str, String>> {
-        let mut pairs = HashMap::new();
+    fn parse_tag_pair_section(&mut self) -> crate::Result<Tags> {
+        let mut pairs = Tags::default();
         while {
             self.eat_whitespace();
             self.eat_self_terminating("[")
         } {
+            let start = self.offset();
             let name = self
                 .read_tag_name()
                 .ok_or("invalid PGN: missing tag name")?;
@@ -252,9 +303,9 @@ impl<'a> PgnParser<'a> {
             if !self.eat_self_terminating("]") {
                 Err("invalid PGN: unclosed tag pair")?
             }
-            if pairs.insert(name, value).is_some() {
-                Err(format!("invalid PGN: \"{}\" key defined twice", name))?
-            }
+            pairs
+                .insert(name, value)
+                .map_err(|error| error.at(start).with_token(name))?;
         }
         Ok(pairs)
     }
@@ -279,14 +330,14 @@ impl<'a> PgnParser<'a> {
     ///
     /// [the specification]: https://ia902908.us.archive.org/26/items/pgn-standard-1994-03-12/PGN_standard_1994-03-12.txt
     fn parse_integer(&mut self) -> Option<usize> {
-        let i = self.content.find(|c: char| !c.is_ascii_digit())?;
+        let i = self
+            .content
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.content.len());
         let (token, remainder) = self.content.split_at(i);
-        if token.is_empty() {
-            return None;
-        }
-        // `token.parse()` should never return `None` because `token` was checked to only contain
-        // ASCII digits.
-        let n = token.parse().unwrap();
+        // `token` only contains ASCII digits, so it can only fail to parse if it is empty or if it
+        // overflows.
+        let n = token.parse().ok()?;
         self.content = remainder;
         Some(n)
     }
@@ -320,10 +371,13 @@ impl<'a> PgnParser<'a> {
     ///
     /// If a movetext SAN-notated move is read, a slice containing the entire SAN-notated turn,
     /// wrapped in `Some`, is returned. Otherwise, `None` is returned.
+    ///
+    /// The move ends at the first white space character, or at the first character that starts a
+    /// different token (e.g., the closing parenthesis of a recursive annotation variation).
     fn read_movetext_san<'b>(&'b mut self) -> Option<&'a str> {
         let i = self
             .content
-            .find(Self::is_whitespace)
+            .find(|c| Self::is_whitespace(c) || matches!(c, '(' | ')' | '{' | ';' | '$'))
             .unwrap_or(self.content.len());
         let (san, remainder) = self.content.split_at(i);
         self.content = remainder;
@@ -350,15 +404,14 @@ impl<'a> PgnParser<'a> {
     /// > The non-negative integer must be from zero to 255 in value.
     ///
     /// [the specification]: https://ia902908.us.archive.org/26/items/pgn-standard-1994-03-12/PGN_standard_1994-03-12.txt
-    fn parse_movetext_nag(&mut self) -> crate::Result<Option<u8>> {
+    fn parse_movetext_nag(&mut self) -> crate::Result<Option<Nag>> {
         if self.eat_self_terminating("$") {
             let message = "invalid PGN: numeric annotation glyph should be a non-negative integer between 0 and 255";
-            Ok(Some(
-                self.parse_integer()
-                    .ok_or(message)?
-                    .try_into()
-                    .map_err(|_| message)?,
-            ))
+            Ok(Some(Nag(self
+                .parse_integer()
+                .ok_or(message)?
+                .try_into()
+                .map_err(|_| message)?)))
         } else {
             Ok(None)
         }
@@ -377,7 +430,9 @@ impl<'a> PgnParser<'a> {
 
     /// Parses an `<element-sequence>`, as defined in section 18 of [the specification].
     ///
-    /// This returns a vector containing the parsed moved from the sequence.
+    /// `first_ply` is the index of the first halfmove of the sequence (see [`Position::ply`]). It is
+    /// used to check move number indications. `depth` is the number of variations the sequence is
+    /// nested in, which is limited to [`MAX_VARIATION_DEPTH`].
     ///
     /// # Definition
     ///
@@ -394,38 +449,79 @@ impl<'a> PgnParser<'a> {
     /// ```
     ///
     /// [the specification]: https://ia902908.us.archive.org/26/items/pgn-standard-1994-03-12/PGN_standard_1994-03-12.txt
-    fn parse_element_sequence(&mut self) -> crate::Result<Vec<AnnotatedAlgebraicTurn>> {
-        let mut turns = Vec::new();
+    fn parse_element_sequence(&mut self, first_ply: usize, depth: usize) -> crate::Result<PgnLine> {
+        let mut comments = Vec::new();
+        let mut moves = Vec::<PgnMove>::new();
+        // Attaches the pending comments to the last move, or to the line if there is no move yet.
+        let mut attach_comments = |parser: &mut Self, moves: &mut Vec<PgnMove>| {
+            let pending = parser.take_comments();
+            match moves.last_mut() {
+                None => comments.extend(pending),
+                Some(m) => m.comments.extend(pending),
+            }
+        };
         while {
             self.advance()?;
             !self.is_element_sequence_end()
         } {
+            attach_comments(self, &mut moves);
             if self.eat_self_terminating("(") {
-                // Recursive annotation variation (section 8.2.5).
+                // Recursive annotation variation (section 8.2.5). It is an alternative to the
+                // last move, so it starts from the same position.
+                let variation_ply = first_ply + moves.len().saturating_sub(1);
+                let Some(last_move) = moves.last_mut() else {
+                    Err("invalid PGN: recursive annotation variation should follow a move")?
+                };
+                if depth == MAX_VARIATION_DEPTH {
+                    Err(format!(
+                        "invalid PGN: recursive annotation variations cannot be nested more than {MAX_VARIATION_DEPTH} times"
+                    ))?
+                }
                 self.advance()?;
-                self.parse_element_sequence()?;
+                let variation = self.parse_element_sequence(variation_ply, depth + 1)?;
                 self.advance()?;
                 if !self.eat_self_terminating(")") {
                     Err("invalid PGN recursive annotation variation: missing closing parenthesis")?
                 }
+                last_move.variations.push(variation);
             } else {
                 // The specification does not allow invalid move number indications.
+                let start = self.offset();
                 if let Some(n) = self.parse_move_number_indication() {
-                    let expected = turns.len() / 2 + 1;
+                    let expected = (first_ply + moves.len()) / 2 + 1;
                     if n != expected {
-                        Err(format!(
+                        Err(Error::from(format!(
                             "invalid PGN: expected move number {} but found {}",
                             expected, n
-                        ))?
+                        ))
+                        .at(start)
+                        .with_token(n))?
                     }
                     self.advance()?;
                 }
-                turns.push(self.parse_movetext_move()?);
+                // Comments between the move number indication and the move precede the move.
+                attach_comments(self, &mut moves);
+                let offset = self.offset();
+                let turn = self
+                    .parse_movetext_move()
+                    .map_err(|error| error.at(offset))?;
+                let mut nags = Vec::from_iter(turn.annotation().map(Nag::from));
                 self.advance()?;
-                self.parse_movetext_nag()?;
+                while let Some(nag) = self.parse_movetext_nag()? {
+                    nags.push(nag);
+                    self.advance()?;
+                }
+                moves.push(PgnMove {
+                    offset,
+                    turn,
+                    nags,
+                    comments: Vec::new(),
+                    variations: Vec::new(),
+                });
             }
         }
-        Ok(turns)
+        attach_comments(self, &mut moves);
+        Ok(PgnLine { comments, moves })
     }
 
     /// Parses a game termination marker.
@@ -443,68 +539,374 @@ impl<'a> PgnParser<'a> {
     /// > without quotes in the movetext.)
     ///
     /// [the specification]: https://ia902908.us.archive.org/26/items/pgn-standard-1994-03-12/PGN_standard_1994-03-12.txt
-    fn eat_game_termination_marker(&mut self) -> bool {
-        self.eat_self_terminating("1-0")
-            || self.eat_self_terminating("0-1")
-            || self.eat_self_terminating("1/2-1/2")
-            || self.eat_self_terminating("*")
+    fn parse_game_termination_marker(&mut self) -> Option<&'static str> {
+        GAME_TERMINATION_MARKERS
+            .into_iter()
+            .find(|marker| self.eat_self_terminating(marker))
     }
 
-    fn parse_movetext_section(&mut self) -> crate::Result<Vec<AnnotatedAlgebraicTurn>> {
-        let turns = self.parse_element_sequence()?;
-        if !self.eat_game_termination_marker() {
+    /// Parses a movetext section, and returns its main line together with its game termination
+    /// marker.
+    fn parse_movetext_section(
+        &mut self,
+        first_ply: usize,
+    ) -> crate::Result<(PgnLine, &'static str)> {
+        let main_line = self.parse_element_sequence(first_ply, 0)?;
+        let Some(result) = self.parse_game_termination_marker() else {
             Err("invalid PGN: game does not terminate properly")?
-        }
-        Ok(turns)
-    }
-}
-
-#[derive(Debug)]
-pub struct PgnGame {
-    /// The starting position, if specified.
-    pub starting_position: Position,
-    /// The successive turns of the game.
-    ///
-    /// Does not contain numeric annotation glyphs.
-    pub turns: Vec<AnnotatedAlgebraicTurn>,
-}
-
-impl PgnGame {
-    /// Returns the number of halfmoves in this game.
-    pub fn len(&self) -> usize {
-        self.turns.len()
+        };
+        Ok((main_line, result))
     }
-}
-
-impl FromStr for PgnGame {
-    type Err = String;
 
-    fn from_str(s: &str) -> crate::Result<Self> {
-        let mut parser = PgnParser::new(s);
-        let tag_pairs = parser.parse_tag_pair_section()?;
-        let turns = parser.parse_movetext_section()?;
-        parser.advance()?;
-        if parser.can_read() {
-            Err("the PGN function accepts a single PGN game")?
-        }
-        let starting_position = match tag_pairs.get("SetUp").map(String::as_ref) {
+    /// Parses the rest of a game whose tag pair section was already parsed.
+    fn parse_game(&mut self, tags: Tags) -> crate::Result<PgnGame> {
+        let starting_position = match tags.get("SetUp") {
             Some("1") => {
-                let fen = tag_pairs
+                let fen = tags
                     .get("FEN")
                     .ok_or("invalid PGN: missing FEN tag (SetUp tag is set to \"1\")")?;
                 parse_fen(fen)?
             }
             Some("0") | None => {
-                if tag_pairs.contains_key("FEN") {
+                if tags.get("FEN").is_some() {
                     Err("warning: PGN contains a FEN tag, but SetUp tag is not set to \"1\"")?
                 }
                 Position::default()
             }
             Some(v) => Err(format!("invalid PGN: illegal value for tag SetUp: {:?}", v))?,
         };
-        Ok(Self {
+        let starting_position = Position {
+            chess960: starting_position.chess960 || tags.is_chess960(),
+            ..starting_position
+        };
+        let (main_line, result) = self.parse_movetext_section(starting_position.ply())?;
+        Ok(PgnGame {
+            tags,
             starting_position,
-            turns,
+            main_line,
+            result,
         })
     }
+
+    /// Skips to the beginning of the game following the one starting at the beginning of `game`.
+    ///
+    /// This is used to recover from errors. The next game is assumed to start at the first line
+    /// beginning with `[` that follows a line of movetext.
+    fn skip_game(&mut self, game: &'a str) {
+        let mut seen_movetext = false;
+        let mut offset = 0;
+        for line in game.split_inclusive('\n') {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with('[') {
+                if seen_movetext {
+                    self.content = &game[offset..];
+                    return;
+                }
+            } else if !trimmed.is_empty() {
+                seen_movetext = true;
+            }
+            offset += line.len();
+        }
+        self.content = "";
+    }
+
+    /// Parses a game of a PGN database, recovering from errors.
+    fn parse_game_entry(&mut self) -> PgnGameEntry {
+        let start = self.content;
+        let (tags, game) = match self.parse_tag_pair_section() {
+            Ok(tags) => (tags.clone(), self.parse_game(tags)),
+            Err(error) => (Tags::default(), Err(error)),
+        };
+        let game = game.map_err(|error| self.locate(error));
+        if game.is_err() {
+            self.skip_game(start);
+        }
+        self.take_comments();
+        PgnGameEntry { tags, game }
+    }
+}
+
+/// A move of a PGN game, together with the alternatives that were given for it.
+#[derive(Debug)]
+pub struct PgnMove {
+    /// The byte offset of the move in the PGN string it was read from.
+    pub offset: usize,
+    pub turn: AnnotatedAlgebraicTurn,
+    /// The numeric annotation glyphs of this move, including its suffix annotation.
+    pub nags: Vec<Nag>,
+    /// The comments following this move.
+    pub comments: Vec<Comment>,
+    /// The recursive annotation variations following this move.
+    ///
+    /// Each variation is an alternative to this move, and therefore starts from the position this
+    /// move is played from.
+    pub variations: Vec<PgnLine>,
+}
+
+/// A sequence of successive moves of a PGN game.
+#[derive(Debug)]
+pub struct PgnLine {
+    /// The comments preceding the first move of this line.
+    pub comments: Vec<Comment>,
+    pub moves: Vec<PgnMove>,
+}
+
+impl PgnLine {
+    /// Replays this line and its variations from a position.
+    ///
+    /// Check and checkmate marks that do not match the positions the moves lead to, and captures
+    /// without a capture indicator, are reported in `warnings`.
+    pub fn replay(
+        &self,
+        starting_position: Position,
+        warnings: &mut Vec<Warning>,
+    ) -> crate::Result<Line> {
+        self.replay_into(Line::new(starting_position), warnings)
+    }
+
+    /// Replays this line and its variations at the end of an empty line, which may have a history
+    /// (see [`Line::branch`]).
+    fn replay_into(&self, mut line: Line, warnings: &mut Vec<Warning>) -> crate::Result<Line> {
+        line.add_comments(self.comments.iter().cloned());
+        for m in &self.moves {
+            let position = line.last_position();
+            let variations = m
+                .variations
+                .iter()
+                .map(|variation| variation.replay_into(line.branch(), warnings))
+                .collect::<crate::Result<_>>()?;
+            let ply = position.ply();
+            let legal_move = m
+                .turn
+                .apply(ply, position)
+                .map_err(|error| error.at(m.offset))?;
+            m.validate_marks(ply, &legal_move, warnings);
+            line.push(legal_move, variations);
+            line.annotate(m.turn.mark(), m.nags.iter().copied());
+            line.add_comments(m.comments.iter().cloned());
+        }
+        Ok(line)
+    }
+}
+
+impl PgnMove {
+    /// Checks that the check and checkmate marks of this move match the position it leads to, and
+    /// that it is marked as a capture if it is one.
+    fn validate_marks(&self, ply: usize, legal_move: &LegalMove, warnings: &mut Vec<Warning>) {
+        let problem = match (self.turn.mark(), legal_move.san().mark()) {
+            (Some(Mark::Check), None) => "is marked as check, but does not give check".into(),
+            (Some(Mark::Check), Some(Mark::Checkmate)) => {
+                "is marked as check, but gives checkmate".into()
+            }
+            (Some(Mark::Checkmate), None) => {
+                "is marked as checkmate, but does not give check".into()
+            }
+            (Some(Mark::Checkmate), Some(Mark::Check)) => {
+                "is marked as checkmate, but only gives check".into()
+            }
+            (None, Some(mark)) => format!("gives {}, but is not marked", mark_name(mark)),
+            _ => String::new(),
+        };
+        if !problem.is_empty() {
+            warnings.push(Warning {
+                ply: Some(ply),
+                message: format!("move {} {problem}", self.turn),
+            });
+        }
+        if self.turn.capture_indicator() == Some(false) && legal_move.is_capture() {
+            warnings.push(Warning {
+                ply: Some(ply),
+                message: format!(
+                    "move {} captures a piece, but is not marked with x",
+                    self.turn
+                ),
+            });
+        }
+    }
+}
+
+/// A game of a PGN database, which may be malformed.
+#[derive(Debug)]
+pub struct PgnGameEntry {
+    /// The tag pairs of the game, or no tag pairs if they could not be parsed.
+    pub tags: Tags,
+    /// The game, or the reason why it could not be parsed.
+    pub game: crate::Result<PgnGame>,
+}
+
+/// Parses PGN data containing any number of games.
+///
+/// An error in a game does not prevent the following games from being parsed.
+pub fn parse_games(s: &str) -> Vec<PgnGameEntry> {
+    let mut parser = PgnParser::new(s);
+    let mut games = Vec::new();
+    loop {
+        if let Err(error) = parser.advance() {
+            games.push(PgnGameEntry {
+                tags: Tags::default(),
+                game: Err(parser.locate(error)),
+            });
+            break;
+        }
+        // Comments between games are not part of any game.
+        parser.take_comments();
+        if !parser.can_read() {
+            break;
+        }
+        games.push(parser.parse_game_entry());
+    }
+    games
+}
+
+#[derive(Debug)]
+pub struct PgnGame {
+    /// The tag pairs of the game.
+    pub tags: Tags,
+    /// The starting position, if specified.
+    pub starting_position: Position,
+    /// The main line of the game.
+    pub main_line: PgnLine,
+    /// The game termination marker (e.g., `1-0`).
+    pub result: &'static str,
+}
+
+impl FromStr for PgnGame {
+    type Err = Error;
+
+    /// Parses a single PGN game.
+    ///
+    /// Errors found when parsing are located in `s`. Errors found when replaying the game are
+    /// not, and should be located using [`Error::with_source`].
+    fn from_str(s: &str) -> crate::Result<Self> {
+        let mut parser = PgnParser::new(s);
+        let mut parse = || {
+            let tags = parser.parse_tag_pair_section()?;
+            let game = parser.parse_game(tags)?;
+            parser.advance()?;
+            if parser.can_read() {
+                Err(
+                    "the PGN function accepts a single PGN game (use `pgn-games` for multiple games)",
+                )?
+            }
+            Ok(game)
+        };
+        let game = parse();
+        game.map_err(|error| parser.locate(error))
+    }
+}
+
+/// Returns the name of a mark, as used in warnings.
+fn mark_name(mark: Mark) -> &'static str {
+    match mark {
+        Mark::Check => "check",
+        Mark::Checkmate => "checkmate",
+    }
+}
+
+/// A problem found in a PGN game that does not prevent it from being read.
+#[derive(Debug)]
+pub struct Warning {
+    /// The index of the halfmove the problem is about (see [`Position::ply`]), or `None` if the
+    /// problem concerns the whole game.
+    ///
+    /// This is the same convention as for errors, with 0 for the first move of White. Problems
+    /// about the final position have the index of the halfmove that would follow it.
+    pub ply: Option<usize>,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn to_json(&self) -> Json {
+        Json::object([
+            ("ply", self.ply.into()),
+            ("message", self.message.as_str().into()),
+        ])
+    }
+}
+
+impl PgnGame {
+    /// Replays the main line of the game.
+    ///
+    /// The warnings about the marks of the moves are returned with the line (see
+    /// [`PgnLine::replay`]).
+    pub fn replay(&self) -> crate::Result<(Line, Vec<Warning>)> {
+        let mut warnings = Vec::new();
+        let line = self
+            .main_line
+            .replay(self.starting_position.clone(), &mut warnings)?;
+        Ok((line, warnings))
+    }
+
+    /// Checks the game for inconsistencies that are accepted when reading it.
+    ///
+    /// `line` and `mark_warnings` are the result of [`PgnGame::replay`]. This adds missing tags of
+    /// the Seven Tag Roster, a `Result` tag that does not match the game termination marker, and a
+    /// game termination marker that does not match the final position to `mark_warnings`.
+    pub fn validate(&self, line: &Line, mark_warnings: Vec<Warning>) -> Vec<Warning> {
+        let mut warnings = SEVEN_TAG_ROSTER
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| self.tags.get(name).is_none())
+            .map(|name| Warning {
+                ply: None,
+                message: format!("missing tag: {name}"),
+            })
+            .collect::<Vec<_>>();
+        if let Some(result) = self.tags.get("Result")
+            && result != self.result
+        {
+            warnings.push(Warning {
+                ply: None,
+                message: format!(
+                    "the Result tag is {result:?}, but the game termination marker is {:?}",
+                    self.result
+                ),
+            })
+        }
+        warnings.extend(mark_warnings);
+        let position = line.last_position();
+        if let Some(termination) = line.termination()
+            && let Some(result) = termination.result(position)
+            && result != self.result
+        {
+            warnings.push(Warning {
+                ply: Some(position.ply()),
+                message: format!(
+                    "the game ended by {}, so its result is {result:?}, but the game termination marker is {:?}",
+                    termination.name(),
+                    self.result
+                ),
+            })
+        }
+        // Warnings are sorted by ply, with the ones about the whole game first.
+        warnings.sort_by_key(|warning| warning.ply);
+        warnings
+    }
+
+    /// Replays the main line of the game and converts the result to JSON, together with the tags.
+    ///
+    /// If `strict` is `true`, the result also contains the warnings found when validating the game
+    /// (see [`PgnGame::validate`]).
+    pub fn to_json(&self, strict: bool) -> crate::Result<Json> {
+        let (line, mark_warnings) = self.replay()?;
+        let time_control = self
+            .tags
+            .get("TimeControl")
+            .and_then(|time_control| time_control.parse::<TimeControl>().ok());
+        let mut json = line.to_json();
+        json.push_entry(
+            "time-spent",
+            Json::array(line.time_spent(time_control.as_ref())),
+        );
+        json.push_entry("tags", self.tags.to_json());
+        json.push_entry("parsed-tags", self.tags.to_parsed_json());
+        if strict {
+            let warnings = self.validate(&line, mark_warnings);
+            json.push_entry(
+                "warnings",
+                Json::array(warnings.iter().map(|w| w.to_json())),
+            );
+        }
+        Ok(json)
+    }
 }
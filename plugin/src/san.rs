@@ -1,10 +1,16 @@
+use crate::bitboard::{
+    bishop_attacks, king_attacks, knight_attacks, pawn_attacks, queen_attacks, rook_attacks,
+};
+use crate::error::{Error, ErrorKind};
 use crate::model::{
-    Color, File, Movement, Piece, PieceKind, Position, Rank, Square, SquareContent,
+    Board, CastlingAvailabilities, Color, File, Movement, Piece, PieceKind, Position, Rank, Side,
+    Square, SquareContent,
 };
 use crate::utils::{CharExt, Finite, Name, StrExt};
+use crate::zobrist::compute_hash;
+use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
-use std::{fmt, iter};
 
 #[derive(Debug, Default, Copy, Clone)]
 enum EnPassantMetadata {
@@ -34,9 +40,9 @@ impl EnPassantMetadata {
 }
 
 #[derive(Debug, Copy, Clone)]
-struct Move<S = Square> {
-    from: S,
-    to: S,
+struct Move {
+    from: Square,
+    to: Square,
     en_passant_metadata: EnPassantMetadata,
     /// `true` if, and only if, this move removes the ability to kingside castle.
     removes_kingside_castling_ability: bool,
@@ -44,8 +50,24 @@ struct Move<S = Square> {
     removes_queenside_castling_ability: bool,
 }
 
-impl<S> Move<S> {
-    fn new(from: S, to: S) -> Self {
+/// What is needed to take back a move made with [`Move::make`].
+#[derive(Debug, Copy, Clone)]
+struct Undo {
+    /// The content of the departure square before the move.
+    moved: SquareContent,
+    /// The square of the captured piece, which is not the destination for en passant captures.
+    capture_square: Square,
+    /// The content of the capture square before the move.
+    captured: SquareContent,
+    castling_availabilities: CastlingAvailabilities,
+    en_passant_target_file: Option<File>,
+    halfmove: u32,
+    fullmove: u32,
+    zobrist: u64,
+}
+
+impl Move {
+    fn new(from: Square, to: Square) -> Self {
         Self {
             from,
             to,
@@ -54,372 +76,274 @@ impl<S> Move<S> {
             removes_queenside_castling_ability: false,
         }
     }
-}
-
-impl Move {
-    pub fn apply(
-        self,
-        position: &Position,
-        piece: PieceKind,
-        capture: bool,
-        promotion: Option<PieceKind>,
-    ) -> Position {
-        let mut new_board = position.board.clone();
-        new_board[self.from] = SquareContent::Empty;
-        let final_piece = Piece::new(position.active, promotion.unwrap_or(piece));
-        new_board[self.to] = SquareContent::Piece(final_piece);
-        if let Some(capture_file) = self.en_passant_metadata.en_passant_capture_file() {
-            let capture_square =
-                Square::new(capture_file, position.active.en_passant_capture_rank());
-            new_board[capture_square] = SquareContent::Empty
-        }
-
-        let mut castling_availabilities = position.castling_availabilities;
-        if self.removes_kingside_castling_ability {
-            if position.active == Color::White {
-                castling_availabilities.white_kingside = false
-            } else {
-                castling_availabilities.black_kingside = false
-            }
-        }
-        if self.removes_queenside_castling_ability {
-            if position.active == Color::White {
-                castling_availabilities.white_queenside = false
-            } else {
-                castling_availabilities.black_queenside = false
-            }
-        }
-
-        let halfmove = if capture || piece == PieceKind::Pawn {
-            0
-        } else {
-            position.halfmove + 1
-        };
-
-        Position {
-            board: new_board,
-            active: position.active.flip(),
-            castling_availabilities,
-            en_passant_target_file: self.en_passant_metadata.skip_file(),
-            halfmove,
-            fullmove: position.next_fullmove(),
-        }
-    }
-}
-
-impl From<Move> for Movement {
-    fn from(m: Move) -> Self {
-        Self::new(m.from, m.to)
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-struct LocalSquare {
-    color: Color,
-    local_file: File,
-    local_rank: Rank,
-}
 
-impl LocalSquare {
-    fn from_absolute(color: Color, absolute_square: Square) -> Self {
-        let local_square = match color {
-            Color::White => absolute_square,
-            Color::Black => absolute_square.transpose(),
-        };
+    fn with_skipped_square(self, square: Square) -> Self {
         Self {
-            color,
-            local_file: local_square.file(),
-            local_rank: local_square.rank(),
+            en_passant_metadata: EnPassantMetadata::SkipFile(square.file()),
+            ..self
         }
     }
 
-    pub fn to_absolute(self) -> Square {
-        let local_square = Square::new(self.local_file, self.local_rank);
-        match self.color {
-            Color::White => local_square,
-            Color::Black => local_square.transpose(),
+    fn with_en_passant_capture(self, square: Square) -> Self {
+        Self {
+            en_passant_metadata: EnPassantMetadata::EnPassantCaptureFile(square.file()),
+            ..self
         }
     }
 
-    pub fn forward(self) -> Option<Self> {
-        self.local_rank
-            .index()
-            .checked_add(1)
-            .and_then(Rank::new)
-            .map(|local_rank| Self { local_rank, ..self })
-    }
-
-    pub fn backward(self) -> Option<Self> {
-        self.local_rank
-            .index()
-            .checked_sub(1)
-            .and_then(Rank::new)
-            .map(|local_rank| Self { local_rank, ..self })
-    }
-
-    pub fn left(self) -> Option<Self> {
-        self.local_file
-            .index()
-            .checked_sub(1)
-            .and_then(File::new)
-            .map(|local_file| Self { local_file, ..self })
-    }
-
-    pub fn right(self) -> Option<Self> {
-        self.local_file
-            .index()
-            .checked_add(1)
-            .and_then(File::new)
-            .map(|local_file| Self { local_file, ..self })
+    /// Tests whether this move captures a piece, including en passant.
+    fn is_capture(self, position: &Position) -> bool {
+        position.at(self.to).is_occupied()
+            || self.en_passant_metadata.en_passant_capture_file().is_some()
     }
 
-    pub fn forward_left(self) -> Option<Self> {
-        self.forward().and_then(Self::left)
-    }
-
-    pub fn forward_right(self) -> Option<Self> {
-        self.forward().and_then(Self::right)
-    }
-
-    pub fn backward_left(self) -> Option<Self> {
-        self.backward().and_then(Self::left)
-    }
-
-    pub fn backward_right(self) -> Option<Self> {
-        self.backward().and_then(Self::right)
-    }
-}
-
-impl From<LocalSquare> for Square {
-    fn from(value: LocalSquare) -> Self {
-        value.to_absolute()
-    }
-}
+    /// Makes this move in a position, in place.
+    ///
+    /// Returns what is needed to take it back with [`Move::unmake`].
+    fn make(self, position: &mut Position, piece: PieceKind, promotion: Option<PieceKind>) -> Undo {
+        let capture = self.is_capture(position);
+        let capture_square = match self.en_passant_metadata.en_passant_capture_file() {
+            Some(file) => Square::new(file, position.active.en_passant_capture_rank()),
+            None => self.to,
+        };
+        let undo = Undo {
+            moved: position.at(self.from),
+            capture_square,
+            captured: position.at(capture_square),
+            castling_availabilities: position.castling_availabilities,
+            en_passant_target_file: position.en_passant_target_file,
+            halfmove: position.halfmove,
+            fullmove: position.fullmove,
+            zobrist: position.zobrist,
+        };
 
-type LocalMove = Move<LocalSquare>;
+        let final_piece = Piece::new(position.active, promotion.unwrap_or(piece));
+        position.board.set(self.from, SquareContent::Empty);
+        position.board.set(capture_square, SquareContent::Empty);
+        position
+            .board
+            .set(self.to, SquareContent::Piece(final_piece));
 
-impl LocalMove {
-    fn with_skipped_square(self, square: LocalSquare) -> Self {
-        Self {
-            en_passant_metadata: EnPassantMetadata::SkipFile(square.to_absolute().file()),
-            ..self
+        let castling_availabilities = &mut position.castling_availabilities;
+        if self.removes_kingside_castling_ability {
+            *castling_availabilities.get_mut(position.active, Side::King) = None
         }
-    }
-
-    fn with_en_passant_capture(self, square: LocalSquare) -> Self {
-        Self {
-            en_passant_metadata: EnPassantMetadata::EnPassantCaptureFile(
-                square.to_absolute().file(),
-            ),
-            ..self
+        if self.removes_queenside_castling_ability {
+            *castling_availabilities.get_mut(position.active, Side::Queen) = None
+        }
+        // Capturing a rook on its initial square removes the ability of the opponent to castle
+        // with it.
+        let opponent = position.active.flip();
+        if self.to.rank() == opponent.home_rank() {
+            for side in [Side::King, Side::Queen] {
+                let availability = castling_availabilities.get_mut(opponent, side);
+                if *availability == Some(self.to.file()) {
+                    *availability = None
+                }
+            }
         }
-    }
 
-    fn with_removed_kingside_castling_availability(self) -> Self {
-        Self {
-            removes_kingside_castling_ability: true,
-            ..self
+        if capture || piece == PieceKind::Pawn {
+            position.halfmove = 0
+        } else {
+            position.halfmove = position.halfmove.saturating_add(1)
         }
+        position.fullmove = position.next_fullmove();
+        position.en_passant_target_file = self.en_passant_metadata.skip_file();
+        position.active = opponent;
+
+        // An en passant capture is only possible next to an opponent pawn, which saves testing
+        // its legality after most two-square pawn moves.
+        let en_passant_file = position.en_passant_target_file.filter(|&file| {
+            let target = Square::new(file, opponent.en_passant_target_rank());
+            let capturing_pawns = pawn_attacks(opponent.flip(), target)
+                & position.board.pieces(Piece::new(opponent, PieceKind::Pawn));
+            !capturing_pawns.is_empty() && can_capture_en_passant(position)
+        });
+        position.zobrist = compute_hash(position, en_passant_file);
+        undo
     }
 
-    fn with_removed_queenside_castling_availability(self) -> Self {
-        Self {
-            removes_queenside_castling_ability: true,
-            ..self
-        }
+    /// Takes back this move, after it was made with [`Move::make`].
+    fn unmake(self, position: &mut Position, undo: Undo) {
+        position.board.set(self.to, SquareContent::Empty);
+        position.board.set(undo.capture_square, undo.captured);
+        position.board.set(self.from, undo.moved);
+        position.active = position.active.flip();
+        position.castling_availabilities = undo.castling_availabilities;
+        position.en_passant_target_file = undo.en_passant_target_file;
+        position.halfmove = undo.halfmove;
+        position.fullmove = undo.fullmove;
+        position.zobrist = undo.zobrist;
     }
 
-    fn with_removed_castling_availabilities(self) -> Self {
-        Self {
-            removes_kingside_castling_ability: true,
-            removes_queenside_castling_ability: true,
-            ..self
-        }
+    /// Tests whether this move does not leave the king of the active player in check.
+    ///
+    /// `scratch` must be equal to the position the move is made in. It is left unchanged.
+    fn is_legal(self, scratch: &mut Position, piece: PieceKind) -> bool {
+        let undo = self.make(scratch, piece, None);
+        let legal = !is_check(scratch);
+        self.unmake(scratch, undo);
+        legal
     }
 }
 
-impl From<LocalMove> for Move {
-    fn from(value: LocalMove) -> Self {
-        Self {
-            from: value.from.into(),
-            to: value.to.into(),
-            en_passant_metadata: value.en_passant_metadata,
-            removes_kingside_castling_ability: value.removes_kingside_castling_ability,
-            removes_queenside_castling_ability: value.removes_queenside_castling_ability,
-        }
+impl From<Move> for Movement {
+    fn from(m: Move) -> Self {
+        Self::new(m.from, m.to)
     }
 }
 
-fn generate_affine_moves<'a>(
-    position: &'a Position,
-    departure: LocalSquare,
-    mut next: impl FnMut(LocalSquare) -> Option<LocalSquare> + 'a,
-) -> impl Iterator<Item = LocalMove> + 'a {
-    let mut state = Some(departure);
-    iter::from_fn(move || {
-        state.and_then(|square| match next(square) {
-            None => {
-                state = None;
-                None
-            }
-            Some(destination) => {
-                state = if position.at(destination.into()).is_occupied() {
-                    None
-                } else {
-                    Some(destination)
-                };
-                Some(LocalMove::new(departure, destination))
-            }
-        })
-    })
+/// The steps of each kind of piece, as `(file, rank)` offsets from White's point of view, in the
+/// order their moves are listed in.
+///
+/// Sliding pieces can repeat their steps.
+const PAWN_STEPS: &[(isize, isize)] = &[(0, 1), (0, 2), (-1, 1), (1, 1)];
+const KNIGHT_STEPS: &[(isize, isize)] = &[
+    (-1, 2),
+    (1, 2),
+    (-1, -2),
+    (1, -2),
+    (-2, 1),
+    (2, 1),
+    (-2, -1),
+    (2, -1),
+];
+const BISHOP_STEPS: &[(isize, isize)] = &[(-1, 1), (1, 1), (-1, -1), (1, -1)];
+const ROOK_STEPS: &[(isize, isize)] = &[(0, 1), (0, -1), (-1, 0), (1, 0)];
+const ROYAL_STEPS: &[(isize, isize)] = &[
+    (0, 1),
+    (0, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (1, 1),
+    (-1, -1),
+    (1, -1),
+];
+
+/// Returns a key that sorts the moves of a piece of the active player in a stable order: by
+/// departure square, file by file, then by step (see [`PAWN_STEPS`] and others), then by distance.
+fn move_order(position: &Position, piece: PieceKind, m: &Move) -> (usize, usize, usize) {
+    let sign = match position.active {
+        Color::White => 1,
+        Color::Black => -1,
+    };
+    let file_offset = sign * (m.to.file().index() as isize - m.from.file().index() as isize);
+    let rank_offset = sign * (m.to.rank().index() as isize - m.from.rank().index() as isize);
+    let distance = file_offset.unsigned_abs().max(rank_offset.unsigned_abs());
+    let (steps, step) = match piece {
+        PieceKind::Pawn => (PAWN_STEPS, (file_offset, rank_offset)),
+        PieceKind::Knight => (KNIGHT_STEPS, (file_offset, rank_offset)),
+        PieceKind::Bishop => (BISHOP_STEPS, (file_offset.signum(), rank_offset.signum())),
+        PieceKind::Rook => (ROOK_STEPS, (file_offset.signum(), rank_offset.signum())),
+        PieceKind::Queen | PieceKind::King => {
+            (ROYAL_STEPS, (file_offset.signum(), rank_offset.signum()))
+        }
+    };
+    let departure = m.from.file().index() * 8 + m.from.rank().index();
+    let step_index = steps.iter().position(|&s| s == step).unwrap_or(steps.len());
+    (departure, step_index, distance)
 }
 
-macro_rules! generate_composite_move {
-    ($moves:ident, $departure:ident, [$( $step:ident ), *]) => {
-        if let Some(destination) = Some($departure)
-            $( .and_then(LocalSquare::$step) )*
-        {
-            $moves.push(LocalMove::new($departure, destination))
-        }
-    }
+/// Returns the square in front of a square, from the point of view of a player.
+fn forward(square: Square, color: Color) -> Option<Square> {
+    let rank = match color {
+        Color::White => square.rank().index().checked_add(1),
+        Color::Black => square.rank().index().checked_sub(1),
+    };
+    Some(Square::new(square.file(), Rank::new(rank?)?))
 }
 
 /// Returns all valid moves pieces of a specific kind can make in a specific position. This does not
 /// include castling moves, and does not exclude moves that put the king in a check position.
+///
+/// Moves to a square occupied by a piece of the active player are never valid.
 fn valid_moves(position: &Position, piece_kind: PieceKind) -> Vec<Move> {
+    let active = position.active;
+    let board = &position.board;
+    let occupied = board.occupied();
+    let pawn_rank = match active {
+        Color::White => Rank::Two,
+        Color::Black => Rank::Seven,
+    };
     let mut moves = Vec::new();
-    for global_square in Square::all() {
-        let departure = LocalSquare::from_absolute(position.active, global_square);
-        if !position
-            .at(departure.into())
-            .is(Piece::new(position.active, piece_kind))
-        {
-            continue;
-        }
-
-        match piece_kind {
+    for departure in board.pieces(Piece::new(active, piece_kind)).squares() {
+        let destinations = match piece_kind {
             PieceKind::Pawn => {
-                // Forward pawn move.
-                if let Some(destination) = departure.forward()
-                    && position.at(destination.into()).is_empty()
-                {
-                    moves.push(LocalMove::new(departure, destination));
-                }
-
-                // Initial two-square pawn move.
-                if let Some(skipped) = departure.forward()
-                    && let Some(destination) = skipped.forward()
-                    && departure.local_rank == Rank::Two
-                    && position.at(skipped.into()).is_empty()
-                    && position.at(destination.into()).is_empty()
-                {
-                    moves.push(LocalMove::new(departure, destination).with_skipped_square(skipped))
-                }
-
-                // Capture with pawn.
-                for destination in departure
-                    .forward_left()
-                    .into_iter()
-                    .chain(departure.forward_right())
+                // Forward pawn move, and initial two-square pawn move.
+                if let Some(skipped) = forward(departure, active)
+                    && !occupied.contains(skipped)
                 {
-                    if position.at(destination.into()).is_occupied() {
-                        moves.push(LocalMove::new(departure, destination))
+                    moves.push(Move::new(departure, skipped));
+                    if let Some(destination) = forward(skipped, active)
+                        && departure.rank() == pawn_rank
+                        && !occupied.contains(destination)
+                    {
+                        moves.push(Move::new(departure, destination).with_skipped_square(skipped))
                     }
+                }
 
-                    if destination.local_rank == Rank::Six
-                        && position.en_passant_target_file == Some(destination.to_absolute().file())
+                // Capture with pawn, including en passant.
+                let attacks = pawn_attacks(active, departure);
+                for destination in attacks.squares() {
+                    if destination.rank() == active.en_passant_target_rank()
+                        && position.en_passant_target_file == Some(destination.file())
                     {
-                        let square = destination.backward().unwrap();
-                        moves.push(
-                            LocalMove::new(departure, destination).with_en_passant_capture(square),
-                        )
+                        let square =
+                            Square::new(destination.file(), active.en_passant_capture_rank());
+                        moves
+                            .push(Move::new(departure, destination).with_en_passant_capture(square))
                     }
                 }
+                attacks & board.by_color(active.flip())
             }
+            PieceKind::Knight => knight_attacks(departure),
+            PieceKind::Bishop => bishop_attacks(departure, occupied),
+            PieceKind::Rook => rook_attacks(departure, occupied),
+            PieceKind::Queen => queen_attacks(departure, occupied),
+            PieceKind::King => king_attacks(departure),
+        };
 
-            PieceKind::Knight => {
-                generate_composite_move!(moves, departure, [forward, forward, left]);
-                generate_composite_move!(moves, departure, [forward, forward, right]);
-                generate_composite_move!(moves, departure, [backward, backward, left]);
-                generate_composite_move!(moves, departure, [backward, backward, right]);
-                generate_composite_move!(moves, departure, [left, left, forward]);
-                generate_composite_move!(moves, departure, [right, right, forward]);
-                generate_composite_move!(moves, departure, [left, left, backward]);
-                generate_composite_move!(moves, departure, [right, right, backward]);
+        let removes_castling_availability = |side| match piece_kind {
+            PieceKind::King => true,
+            PieceKind::Rook => {
+                departure.rank() == active.home_rank()
+                    && position.castling_availabilities.get(active, side) == Some(departure.file())
             }
-
-            #[rustfmt::skip]
-            PieceKind::Bishop => moves.extend(
-                iter::empty()
-                    .chain(generate_affine_moves(position, departure, LocalSquare::forward_left))
-                    .chain(generate_affine_moves(position, departure, LocalSquare::forward_right))
-                    .chain(generate_affine_moves(position, departure, LocalSquare::backward_left))
-                    .chain(generate_affine_moves(position, departure, LocalSquare::backward_right)),
-            ),
-            #[rustfmt::skip]
-            PieceKind::Rook => moves.extend(
-                iter::empty()
-                    .chain(generate_affine_moves(position, departure, LocalSquare::forward))
-                    .chain(generate_affine_moves(position, departure, LocalSquare::backward))
-                    .chain(generate_affine_moves(position, departure, LocalSquare::left))
-                    .chain(generate_affine_moves(position, departure, LocalSquare::right))
-                    .map(|m| {
-                        let departure_file = Square::from(m.from).file();
-                        if departure_file == File::H {
-                            m.with_removed_kingside_castling_availability()
-                        } else if departure_file == File::A {
-                            m.with_removed_queenside_castling_availability()
-                        } else {
-                            m
-                        }
-                    }),
-            ),
-            #[rustfmt::skip]
-            PieceKind::Queen => moves.extend(
-                iter::empty()
-                    .chain(generate_affine_moves(position, departure, LocalSquare::forward))
-                    .chain(generate_affine_moves(position, departure, LocalSquare::backward))
-                    .chain(generate_affine_moves(position, departure, LocalSquare::left))
-                    .chain(generate_affine_moves(position, departure, LocalSquare::right))
-                    .chain(generate_affine_moves(position, departure, LocalSquare::forward_left))
-                    .chain(generate_affine_moves(position, departure, LocalSquare::forward_right))
-                    .chain(generate_affine_moves(position, departure, LocalSquare::backward_left))
-                    .chain(generate_affine_moves(position, departure, LocalSquare::backward_right)),
-            ),
-            PieceKind::King => moves.extend(
-                iter::empty()
-                    .chain(departure.forward())
-                    .chain(departure.backward())
-                    .chain(departure.left())
-                    .chain(departure.right())
-                    .chain(departure.forward_left())
-                    .chain(departure.forward_right())
-                    .chain(departure.backward_left())
-                    .chain(departure.backward_right())
-                    .map(|destination| {
-                        LocalMove::new(departure, destination)
-                            .with_removed_castling_availabilities()
-                    }),
-            ),
+            _ => false,
+        };
+        let removes_kingside_castling_ability = removes_castling_availability(Side::King);
+        let removes_queenside_castling_ability = removes_castling_availability(Side::Queen);
+        for destination in (destinations & !board.by_color(active)).squares() {
+            moves.push(Move {
+                removes_kingside_castling_ability,
+                removes_queenside_castling_ability,
+                ..Move::new(departure, destination)
+            })
         }
     }
+    moves.sort_by_key(|m| move_order(position, piece_kind, m));
+    moves
+}
 
-    moves.into_iter().map(|m| m.into()).collect()
+/// Tests whether the king of a player is attacked by an adversary piece.
+fn is_king_attacked(board: &Board, color: Color) -> bool {
+    board
+        .pieces(Piece::new(color, PieceKind::King))
+        .squares()
+        .any(|square| board.is_attacked(square, color.flip()))
 }
 
 /// Tests whether the active player's king is threatened by an adversary piece
 /// in a position.
 fn is_check(position: &Position) -> bool {
-    let king = Piece::new(position.active.flip(), PieceKind::King);
-    PieceKind::iter()
-        .flat_map(|piece| valid_moves(position, piece))
-        .any(|m| position.at(m.to).is(king))
+    is_king_attacked(&position.board, position.active.flip())
 }
 
-#[derive(Debug, Copy, Clone)]
-pub enum Side {
-    King,
-    Queen,
+/// Tests whether the active player's king is in check in a position.
+pub fn is_in_check(position: &Position) -> bool {
+    is_king_attacked(&position.board, position.active)
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -434,6 +358,17 @@ pub enum AlgebraicTurn {
         promotion: Option<PieceKind>,
     },
     Castle(Side),
+    /// A move described by its departure and destination squares, as in long algebraic notation
+    /// (e.g., "Ng1-f3") or in UCI notation (e.g., "g1f3").
+    ///
+    /// Such a turn is resolved to one of the other variants using the position it is applied to.
+    Coordinate {
+        piece: Option<PieceKind>,
+        departure: Square,
+        destination: Square,
+        capture: bool,
+        promotion: Option<PieceKind>,
+    },
 }
 
 impl AlgebraicTurn {
@@ -446,11 +381,87 @@ impl AlgebraicTurn {
         )
     }
 
-    pub fn apply(
+    /// Returns why the promotion or the capture indicator of a normal turn do not match the
+    /// position it is applied to, if they do not.
+    ///
+    /// A capture written without a capture indicator is accepted.
+    fn inconsistency(self, position: &Position) -> Option<String> {
+        let Self::Normal {
+            destination_file,
+            destination_rank,
+            piece,
+            capture,
+            promotion,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        let destination = Square::new(destination_file, destination_rank);
+        let reaches_last_rank =
+            piece == PieceKind::Pawn && destination_rank == position.active.promotion_rank();
+        let promotion_problem = match promotion {
+            Some(_) if piece != PieceKind::Pawn => Some("only pawns can be promoted"),
+            Some(_) if !reaches_last_rank => Some("pawns can only be promoted on the last rank"),
+            Some(PieceKind::Pawn | PieceKind::King) => {
+                Some("pawns can only be promoted to a queen, a rook, a bishop, or a knight")
+            }
+            None if reaches_last_rank => Some("a pawn reaching the last rank must be promoted"),
+            _ => None,
+        };
+        if let Some(problem) = promotion_problem {
+            return Some(problem.into());
+        }
+        let en_passant = piece == PieceKind::Pawn
+            && position.en_passant_target_file == Some(destination_file)
+            && destination_rank == position.active.en_passant_target_rank();
+        if capture && position.at(destination).is_empty() && !en_passant {
+            return Some(format!("there is no piece to capture on {destination}"));
+        }
+        None
+    }
+
+    /// Creates the error for this turn when it cannot be applied to a position.
+    ///
+    /// `target` is the kind of the piece this turn moves and its destination, if known. It is used
+    /// to suggest close legal moves. `leaves_king_in_check` indicates whether the move is only
+    /// illegal because it leaves the king in check.
+    fn illegal(
         self,
         turn_index: usize,
-        initial_position: &Position,
-    ) -> crate::Result<(Position, Movement)> {
+        position: &Position,
+        target: Option<(PieceKind, Square)>,
+        leaves_king_in_check: bool,
+    ) -> Error {
+        let mut message = format!("illegal move: {}", self.to_indexed_string(turn_index));
+        if leaves_king_in_check {
+            message.push_str(" (it leaves the king in check)");
+        } else if let Some((piece, destination)) = target {
+            let suggestions = closest_legal_moves(position, piece, destination);
+            if !suggestions.is_empty() {
+                message.push_str(&format!(
+                    " (did you mean {}?)",
+                    join_alternatives(&suggestions)
+                ));
+            }
+        }
+        message.into()
+    }
+
+    /// Applies this turn to a position.
+    ///
+    /// `turn_index` is the index of the halfmove this turn is (see [`Position::ply`]). It is used
+    /// in error messages.
+    pub fn apply(self, turn_index: usize, initial_position: &Position) -> crate::Result<LegalMove> {
+        self.resolve(turn_index, initial_position).map_err(|error| {
+            error
+                .in_context(ErrorKind::IllegalMove)
+                .at_ply(turn_index)
+                .with_token(self)
+        })
+    }
+
+    fn resolve(self, turn_index: usize, initial_position: &Position) -> crate::Result<LegalMove> {
         match self {
             Self::Normal {
                 destination_file,
@@ -458,107 +469,240 @@ impl AlgebraicTurn {
                 piece,
                 departure_file,
                 departure_rank,
-                capture,
                 promotion,
+                ..
             } => {
-                let mut possibilities = valid_moves(initial_position, piece)
+                if let Some(reason) = self.inconsistency(initial_position) {
+                    Err(format!(
+                        "illegal move: {} ({reason})",
+                        self.to_indexed_string(turn_index)
+                    ))?
+                }
+                let destination = Square::new(destination_file, destination_rank);
+                let matches_departure = |m: &Move| {
+                    departure_file.is_none_or(|file| m.from.file() == file)
+                        && departure_rank.is_none_or(|rank| m.from.rank() == rank)
+                };
+                // All the moves the piece can make to the destination, regardless of the
+                // departure square, including those that put the king in a check position.
+                let mut scratch = initial_position.clone();
+                let (candidates, checks) = valid_moves(initial_position, piece)
                     .into_iter()
-                    // Filter out moves that do not match the algebraic notation.
-                    .filter(|m| {
-                        m.to.file() == destination_file
-                            && m.to.rank() == destination_rank
-                            && departure_file.is_none_or(|file| m.from.file() == file)
-                            && departure_rank.is_none_or(|rank| m.from.rank() == rank)
+                    .filter(|m| m.to == destination)
+                    .partition::<Vec<_>, _>(|m| m.is_legal(&mut scratch, piece));
+                // The departures of the legal ones are used to disambiguate the canonical turn.
+                let departures = candidates.iter().map(|m| m.from).collect::<Vec<_>>();
+                let possibilities = candidates
+                    .into_iter()
+                    .filter(matches_departure)
+                    .map(|m| {
+                        let mut legal_move =
+                            LegalMove::normal(initial_position, m, piece, promotion);
+                        legal_move.turn = legal_move.minimal_turn(&departures);
+                        legal_move
                     })
-                    // Get corresponding positions.
-                    .map(|m| (m.apply(initial_position, piece, capture, promotion), m))
-                    // Filter out moves that put the king in a check position.
-                    .filter(|(new_position, _)| !is_check(new_position));
-
-                match (possibilities.next(), possibilities.next()) {
-                    (Some((new_position, m)), None) => Ok((new_position, m.into())),
-                    (None, _) => Err(format!(
-                        "illegal move: {}",
-                        self.to_indexed_string(turn_index)
-                    )),
-                    (Some(_), Some(_)) => Err(format!(
-                        "ambiguous move: {}",
-                        self.to_indexed_string(turn_index)
+                    .collect::<Vec<_>>();
+
+                match &possibilities[..] {
+                    [m] => Ok(m.clone()),
+                    [] => Err(self.illegal(
+                        turn_index,
+                        initial_position,
+                        Some((piece, destination)),
+                        checks.iter().any(matches_departure),
                     )),
+                    _ => Err(format!(
+                        "ambiguous move: {} (could be {})",
+                        self.to_indexed_string(turn_index),
+                        join_alternatives(&possibilities)
+                    )
+                    .into()),
                 }
             }
 
-            Self::Castle(side) => {
-                let rank = match initial_position.active {
-                    Color::White => Rank::One,
-                    Color::Black => Rank::Eight,
+            Self::Coordinate {
+                piece,
+                departure,
+                destination,
+                capture,
+                promotion,
+            } => {
+                let SquareContent::Piece(moved) = initial_position.at(departure) else {
+                    let target = piece.map(|piece| (piece, destination));
+                    Err(self.illegal(turn_index, initial_position, target, false))?
                 };
-                let king = Piece::new(initial_position.active, PieceKind::King);
-                let rook = Piece::new(initial_position.active, PieceKind::Rook);
-
-                let (initial_king_square, initial_rook_square, new_king_square, new_rook_square) =
-                    match side {
-                        Side::King => (
-                            Square::new(File::E, rank),
-                            Square::new(File::H, rank),
-                            Square::new(File::G, rank),
-                            Square::new(File::F, rank),
-                        ),
-                        Side::Queen => (
-                            Square::new(File::E, rank),
-                            Square::new(File::A, rank),
-                            Square::new(File::C, rank),
-                            Square::new(File::D, rank),
-                        ),
-                    };
-
-                let common_requirements = initial_position.at(initial_king_square).is(king)
-                    && initial_position.at(initial_rook_square).is(rook)
-                    && initial_position.at(new_king_square).is_empty()
-                    && initial_position.at(new_rook_square).is_empty();
-                let requirements = common_requirements
-                    && match side {
-                        Side::King => initial_position
-                            .castling_availabilities
-                            .kingside_for(initial_position.active),
-                        Side::Queen => {
-                            initial_position
-                                .castling_availabilities
-                                .queenside_for(initial_position.active)
-                                && initial_position.at(Square::new(File::B, rank)).is_empty()
+                if moved.color != initial_position.active || piece.is_some_and(|p| p != moved.kind)
+                {
+                    let target = Some((piece.unwrap_or(moved.kind), destination));
+                    Err(self.illegal(turn_index, initial_position, target, false))?
+                }
+                let home_rank = initial_position.active.home_rank();
+                let on_home_rank = departure.rank() == home_rank && destination.rank() == home_rank;
+                let onto_own_rook = initial_position
+                    .at(destination)
+                    .is(Piece::new(moved.color, PieceKind::Rook));
+                let turn = match (moved.kind, departure.file(), destination.file()) {
+                    // Castling given as a king move onto the rook, as in Chess960.
+                    (PieceKind::King, from, to)
+                        if initial_position.chess960 && on_home_rank && onto_own_rook =>
+                    {
+                        match to.index() > from.index() {
+                            true => Self::Castle(Side::King),
+                            false => Self::Castle(Side::Queen),
                         }
-                    };
-                if !requirements {
+                    }
+                    // Castling given as a king move.
+                    (PieceKind::King, File::E, File::G)
+                        if !initial_position.chess960 && on_home_rank =>
+                    {
+                        Self::Castle(Side::King)
+                    }
+                    (PieceKind::King, File::E, File::C)
+                        if !initial_position.chess960 && on_home_rank =>
+                    {
+                        Self::Castle(Side::Queen)
+                    }
+                    _ => Self::Normal {
+                        destination_file: destination.file(),
+                        destination_rank: destination.rank(),
+                        piece: moved.kind,
+                        departure_file: Some(departure.file()),
+                        departure_rank: Some(departure.rank()),
+                        capture,
+                        promotion,
+                    },
+                };
+                if let Some(reason) = turn.inconsistency(initial_position) {
                     Err(format!(
-                        "illegal move: {}",
+                        "illegal move: {} ({reason})",
                         self.to_indexed_string(turn_index)
                     ))?
                 }
-
-                let mut new_board = initial_position.board.clone();
-                new_board[initial_king_square] = SquareContent::Empty;
-                new_board[new_king_square] = SquareContent::Piece(king);
-                new_board[initial_rook_square] = SquareContent::Empty;
-                new_board[new_rook_square] = SquareContent::Piece(rook);
-                let new_position = Position {
-                    board: new_board,
-                    active: initial_position.active.flip(),
-                    castling_availabilities: initial_position
-                        .castling_availabilities
-                        .remove_for(initial_position.active),
-                    en_passant_target_file: None,
-                    halfmove: initial_position.halfmove + 1,
-                    fullmove: initial_position.next_fullmove(),
-                };
-
-                let movement = Movement::new(initial_king_square, new_king_square);
-
-                Ok((new_position, movement))
+                turn.resolve(turn_index, initial_position).map_err(|_| {
+                    let mut scratch = initial_position.clone();
+                    let leaves_king_in_check = valid_moves(initial_position, moved.kind)
+                        .into_iter()
+                        .filter(|m| m.from == departure && m.to == destination)
+                        .any(|m| !m.is_legal(&mut scratch, moved.kind));
+                    self.illegal(
+                        turn_index,
+                        initial_position,
+                        Some((moved.kind, destination)),
+                        leaves_king_in_check,
+                    )
+                })
             }
+
+            Self::Castle(side) => castle(initial_position, side).ok_or_else(|| {
+                let king_destination = Square::new(
+                    match side {
+                        Side::King => File::G,
+                        Side::Queen => File::C,
+                    },
+                    initial_position.active.home_rank(),
+                );
+                self.illegal(
+                    turn_index,
+                    initial_position,
+                    Some((PieceKind::King, king_destination)),
+                    false,
+                )
+            }),
         }
     }
 }
 
+/// Castles on a side in a position.
+///
+/// The king moves to the g-file or the c-file, and the rook it castles with moves to the square
+/// the king crossed. In standard chess, the king must start on the e-file and the rook in the
+/// corner. In Chess960, they can start on any file, and either piece can stay where it is.
+///
+/// Returns `None` if castling on this side is not possible.
+fn castle(position: &Position, side: Side) -> Option<LegalMove> {
+    let rank = position.active.home_rank();
+    let king = Piece::new(position.active, PieceKind::King);
+    let rook = Piece::new(position.active, PieceKind::Rook);
+
+    let rook_file = position
+        .castling_availabilities
+        .get(position.active, side)?;
+    let king_file = position.board.home_king_file(position.active)?;
+    let (new_king_file, new_rook_file, standard_rook_file) = match side {
+        Side::King => (File::G, File::F, File::H),
+        Side::Queen => (File::C, File::D, File::A),
+    };
+    if !position.chess960 && (king_file != File::E || rook_file != standard_rook_file) {
+        return None;
+    }
+    let initial_king_square = Square::new(king_file, rank);
+    let initial_rook_square = Square::new(rook_file, rank);
+    let new_king_square = Square::new(new_king_file, rank);
+    let new_rook_square = Square::new(new_rook_file, rank);
+
+    let on_side = match side {
+        Side::King => rook_file.index() > king_file.index(),
+        Side::Queen => rook_file.index() < king_file.index(),
+    };
+    // Apart from the king and the rook, all the squares they move through or to must be empty.
+    let files = [king_file, rook_file, new_king_file, new_rook_file].map(File::index);
+    let path_is_empty = (files.into_iter().min()?..=files.into_iter().max()?)
+        .filter_map(File::new)
+        .map(|file| Square::new(file, rank))
+        .filter(|&square| square != initial_king_square && square != initial_rook_square)
+        .all(|square| position.at(square).is_empty());
+    if !on_side || !position.at(initial_rook_square).is(rook) || !path_is_empty {
+        return None;
+    }
+    // The king cannot castle out of check, nor through or into a square attacked by an opponent
+    // piece.
+    let mut board_without_castling_pieces = position.board.clone();
+    board_without_castling_pieces.set(initial_king_square, SquareContent::Empty);
+    board_without_castling_pieces.set(initial_rook_square, SquareContent::Empty);
+    let (first_file, last_file) = match king_file.index() < new_king_file.index() {
+        true => (king_file, new_king_file),
+        false => (new_king_file, king_file),
+    };
+    let path_is_safe = (first_file.index()..=last_file.index())
+        .filter_map(File::new)
+        .all(|file| {
+            !board_without_castling_pieces
+                .is_attacked(Square::new(file, rank), position.active.flip())
+        });
+    if !path_is_safe {
+        return None;
+    }
+
+    let mut new_position = position.clone();
+    new_position
+        .board
+        .set(initial_king_square, SquareContent::Empty);
+    new_position
+        .board
+        .set(initial_rook_square, SquareContent::Empty);
+    new_position
+        .board
+        .set(new_king_square, SquareContent::Piece(king));
+    new_position
+        .board
+        .set(new_rook_square, SquareContent::Piece(rook));
+    new_position.active = position.active.flip();
+    new_position.castling_availabilities =
+        position.castling_availabilities.remove_for(position.active);
+    new_position.en_passant_target_file = None;
+    new_position.halfmove = position.halfmove.saturating_add(1);
+    new_position.fullmove = position.next_fullmove();
+    new_position.zobrist = compute_hash(&new_position, None);
+
+    let movement = Movement::new(initial_king_square, new_king_square);
+
+    Some(LegalMove {
+        turn: AlgebraicTurn::Castle(side),
+        movement,
+        position: new_position,
+    })
+}
+
 impl Display for AlgebraicTurn {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
@@ -596,19 +740,39 @@ impl Display for AlgebraicTurn {
                     "{piece_text}{departure_file_text}{departure_rank_text}{capture_text}{destination_file}{destination_rank}{promote_text}",
                 )
             }
-            Self::Castle(Side::King) => write!(f, "0-0"),
-            Self::Castle(Side::Queen) => write!(f, "0-0-0"),
+            Self::Castle(Side::King) => write!(f, "O-O"),
+            Self::Castle(Side::Queen) => write!(f, "O-O-O"),
+            Self::Coordinate {
+                piece,
+                departure,
+                destination,
+                capture,
+                promotion,
+            } => {
+                if let Some(piece) = piece.filter(|&p| p != PieceKind::Pawn) {
+                    write!(f, "{piece}")?
+                }
+                write!(
+                    f,
+                    "{departure}{}{destination}",
+                    if *capture { "x" } else { "-" }
+                )?;
+                if let Some(promotion) = promotion {
+                    write!(f, "={promotion}")?
+                }
+                Ok(())
+            }
         }
     }
 }
 
 fn parse_finite<T: Finite + Name>(source: &str) -> (&str, Option<T>) {
-    for x in T::values() {
-        if let Some(prefix) = source.strip_suffix(&x.name()) {
-            return (prefix, Some(x));
-        }
-    }
-    (source, None)
+    // The longest name wins, so that `!?` is not read as `?`.
+    T::values()
+        .into_iter()
+        .filter_map(|x| Some((source.strip_suffix(&x.name())?, x)))
+        .min_by_key(|(prefix, _)| prefix.len())
+        .map_or((source, None), |(prefix, x)| (prefix, Some(x)))
 }
 
 fn parse_promotion(source: &str) -> crate::Result<(&str, Option<PieceKind>)> {
@@ -652,6 +816,14 @@ fn parse_capture(source: &str) -> (&str, bool) {
     }
 }
 
+/// Strips a leading capture indicator from a string, if there is one.
+fn parse_capture_prefix(source: &str) -> Option<&str> {
+    source
+        .strip_prefix('x')
+        .or_else(|| source.strip_prefix(':'))
+        .or_else(|| source.strip_prefix('×'))
+}
+
 fn parse_piece(source: &str) -> (&str, PieceKind) {
     match source.split_last_char() {
         Some((prefix, 'N')) => (prefix, PieceKind::Knight),
@@ -663,8 +835,47 @@ fn parse_piece(source: &str) -> (&str, PieceKind) {
     }
 }
 
+/// Parses a turn in long algebraic notation (e.g., "Ng1-f3", "Ng1xf3", "e7e8=Q") or in UCI
+/// notation (e.g., "g1f3", "e7e8q").
+///
+/// Returns `None` if the turn does not use one of these notations.
+fn parse_coordinate_turn(source: &str) -> Option<AlgebraicTurn> {
+    let (piece, s) = match source.chars().next()? {
+        c @ ('N' | 'B' | 'R' | 'Q' | 'K') => (Some(c.parse().ok()?), &source[1..]),
+        _ => (None, source),
+    };
+    let departure = s.get(..2)?.parse().ok()?;
+    let s = &s[2..];
+    let (s, capture) = match s.strip_prefix('-') {
+        Some(s) => (s, false),
+        None => match parse_capture_prefix(s) {
+            Some(s) => (s, true),
+            None => (s, false),
+        },
+    };
+    let destination = s.get(..2)?.parse().ok()?;
+    let s = &s[2..];
+    let promotion = if s.is_empty() {
+        None
+    } else {
+        let s = s.strip_prefix(['=', '/']).unwrap_or(s);
+        match s.chars().collect::<Vec<_>>()[..] {
+            // UCI notation uses lower-case letters for promotions.
+            [c] | ['(', c, ')'] => Some(c.to_ascii_uppercase().parse().ok()?),
+            _ => return None,
+        }
+    };
+    Some(AlgebraicTurn::Coordinate {
+        piece,
+        departure,
+        destination,
+        capture,
+        promotion,
+    })
+}
+
 impl FromStr for AlgebraicTurn {
-    type Err = String;
+    type Err = Error;
 
     fn from_str(source: &str) -> crate::Result<Self> {
         if source == "0-0" || source == "O-O" {
@@ -675,6 +886,10 @@ impl FromStr for AlgebraicTurn {
             return Ok(Self::Castle(Side::Queen));
         }
 
+        if let Some(turn) = parse_coordinate_turn(source) {
+            return Ok(turn);
+        }
+
         // TODO: Support pawn moves containing only file information (minimal algebraic notation).
 
         // TODO: Support "e.p." suffix.
@@ -719,6 +934,19 @@ pub enum Mark {
     Checkmate,
 }
 
+impl Mark {
+    /// Returns the mark a move leading to a specific position should have.
+    fn of(position: &Position) -> Option<Self> {
+        if !is_in_check(position) {
+            None
+        } else if has_legal_move(position) {
+            Some(Self::Check)
+        } else {
+            Some(Self::Checkmate)
+        }
+    }
+}
+
 impl Finite for Mark {
     fn values() -> [Self; 2] {
         [Self::Check, Self::Checkmate]
@@ -773,19 +1001,19 @@ impl Name for Annotation {
 #[derive(Debug, Copy, Clone)]
 pub struct AnnotatedAlgebraicTurn {
     turn: AlgebraicTurn,
-    #[allow(unused)]
     mark: Option<Mark>,
-    #[allow(unused)]
     annotation: Option<Annotation>,
 }
 
 impl FromStr for AnnotatedAlgebraicTurn {
-    type Err = String;
+    type Err = Error;
 
-    fn from_str(s: &str) -> crate::Result<Self> {
-        let (s, annotation) = parse_finite(s);
+    fn from_str(source: &str) -> crate::Result<Self> {
+        let (s, annotation) = parse_finite(source);
         let (s, mark) = parse_finite(s);
-        let turn = s.parse()?;
+        let turn = s
+            .parse::<AlgebraicTurn>()
+            .map_err(|error| error.in_context(ErrorKind::Notation).with_token(source))?;
 
         Ok(Self {
             turn,
@@ -795,16 +1023,448 @@ impl FromStr for AnnotatedAlgebraicTurn {
     }
 }
 
+impl Display for AnnotatedAlgebraicTurn {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.turn)?;
+        if let Some(mark) = self.mark {
+            write!(f, "{}", mark.name())?
+        }
+        if let Some(annotation) = self.annotation {
+            write!(f, "{}", annotation.name())?
+        }
+        Ok(())
+    }
+}
+
 impl AnnotatedAlgebraicTurn {
-    pub fn apply(
-        self,
-        turn_index: usize,
-        position: &Position,
-    ) -> crate::Result<(Position, Movement)> {
+    /// Returns the check or checkmate mark, as written.
+    pub fn mark(&self) -> Option<Mark> {
+        self.mark
+    }
+
+    /// Returns whether this turn has a capture indicator (`x`), or `None` if it is not written in
+    /// standard algebraic notation.
+    pub fn capture_indicator(&self) -> Option<bool> {
+        match self.turn {
+            AlgebraicTurn::Normal { capture, .. } => Some(capture),
+            _ => None,
+        }
+    }
+
+    /// Returns the move suffix annotation (`!`, `?`, etc.), as written.
+    pub fn annotation(&self) -> Option<Annotation> {
+        self.annotation
+    }
+
+    pub fn apply(self, turn_index: usize, position: &Position) -> crate::Result<LegalMove> {
         self.turn.apply(turn_index, position)
     }
 }
 
+/// A legal move, together with the position it leads to.
+#[derive(Debug, Clone)]
+pub struct LegalMove {
+    /// The canonical turn corresponding to this move.
+    turn: AlgebraicTurn,
+    pub movement: Movement,
+    pub position: Position,
+}
+
+impl LegalMove {
+    /// Creates a legal move from a normal move.
+    ///
+    /// The departure square is fully specified in the corresponding turn. Use [`disambiguate`] to
+    /// make the turn canonical.
+    fn normal(
+        position: &Position,
+        m: Move,
+        piece: PieceKind,
+        promotion: Option<PieceKind>,
+    ) -> Self {
+        let capture = m.is_capture(position);
+        let mut new_position = position.clone();
+        m.make(&mut new_position, piece, promotion);
+        Self {
+            turn: AlgebraicTurn::Normal {
+                destination_file: m.to.file(),
+                destination_rank: m.to.rank(),
+                piece,
+                departure_file: Some(m.from.file()),
+                departure_rank: Some(m.from.rank()),
+                capture,
+                promotion,
+            },
+            movement: m.into(),
+            position: new_position,
+        }
+    }
+
+    /// Returns the turn corresponding to this move, specifying only the parts of the departure
+    /// square that are necessary to distinguish it from the other legal moves of the same kind of
+    /// piece to the same destination, whose departures are in `departures`.
+    fn minimal_turn(&self, departures: &[Square]) -> AlgebraicTurn {
+        let AlgebraicTurn::Normal {
+            destination_file,
+            destination_rank,
+            piece,
+            capture,
+            promotion,
+            ..
+        } = self.turn
+        else {
+            return self.turn;
+        };
+        let departure = self.movement.from();
+        let (departure_file, departure_rank) = if piece == PieceKind::Pawn {
+            (capture.then_some(departure.file()), None)
+        } else {
+            let rivals = departures
+                .iter()
+                .filter(|&&square| square != departure)
+                .collect::<Vec<_>>();
+            if rivals.is_empty() {
+                (None, None)
+            } else if rivals.iter().all(|s| s.file() != departure.file()) {
+                (Some(departure.file()), None)
+            } else if rivals.iter().all(|s| s.rank() != departure.rank()) {
+                (None, Some(departure.rank()))
+            } else {
+                (Some(departure.file()), Some(departure.rank()))
+            }
+        };
+        AlgebraicTurn::Normal {
+            destination_file,
+            destination_rank,
+            piece,
+            departure_file,
+            departure_rank,
+            capture,
+            promotion,
+        }
+    }
+
+    /// Tests whether this move captures a piece, including en passant.
+    pub fn is_capture(&self) -> bool {
+        matches!(self.turn, AlgebraicTurn::Normal { capture: true, .. })
+    }
+
+    /// Returns the canonical SAN of this move, including the check or checkmate mark.
+    pub fn san(&self) -> AnnotatedAlgebraicTurn {
+        AnnotatedAlgebraicTurn {
+            turn: self.turn,
+            mark: Mark::of(&self.position),
+            annotation: None,
+        }
+    }
+}
+
+/// Makes the turns of some moves canonical, assuming `moves` contains all the legal moves that
+/// could be confused with one another.
+fn disambiguate(moves: &mut [LegalMove]) {
+    let turns = moves
+        .iter()
+        .map(|m| {
+            let departures = moves
+                .iter()
+                .filter(|other| {
+                    matches!(
+                        (m.turn, other.turn),
+                        (
+                            AlgebraicTurn::Normal { piece: p, .. },
+                            AlgebraicTurn::Normal { piece: q, .. },
+                        ) if p == q
+                    ) && other.movement.to() == m.movement.to()
+                })
+                .map(|other| other.movement.from())
+                .collect::<Vec<_>>();
+            m.minimal_turn(&departures)
+        })
+        .collect::<Vec<_>>();
+    for (m, turn) in moves.iter_mut().zip(turns) {
+        m.turn = turn
+    }
+}
+
+/// Returns an iterator over the legal normal moves in a position, together with the kind of the
+/// piece they move and the kind of piece a pawn is promoted to, if any.
+///
+/// Moves are made and taken back on a copy of the position to test their legality, so that no
+/// new position is created.
+fn legal_normal_moves(
+    position: &Position,
+) -> impl Iterator<Item = (Move, PieceKind, Option<PieceKind>)> {
+    let mut scratch = position.clone();
+    PieceKind::iter()
+        .flat_map(move |piece| {
+            valid_moves(position, piece)
+                .into_iter()
+                .map(move |m| (m, piece))
+        })
+        .filter(move |&(m, piece)| m.is_legal(&mut scratch, piece))
+        .flat_map(move |(m, piece)| {
+            let promotions =
+                if piece == PieceKind::Pawn && m.to.rank() == position.active.promotion_rank() {
+                    vec![
+                        Some(PieceKind::Queen),
+                        Some(PieceKind::Rook),
+                        Some(PieceKind::Bishop),
+                        Some(PieceKind::Knight),
+                    ]
+                } else {
+                    vec![None]
+                };
+            promotions
+                .into_iter()
+                .map(move |promotion| (m, piece, promotion))
+        })
+}
+
+/// Returns an iterator over the legal castling moves in a position.
+fn legal_castling_moves(position: &Position) -> impl Iterator<Item = LegalMove> {
+    [Side::King, Side::Queen]
+        .into_iter()
+        .filter_map(|side| castle(position, side))
+        .filter(|m| !is_check(&m.position))
+}
+
+/// Returns an iterator over the legal moves in a position, including castling moves.
+///
+/// The turns of normal moves have their departure square fully specified.
+fn iter_legal_moves(position: &Position) -> impl Iterator<Item = LegalMove> {
+    legal_normal_moves(position)
+        .map(|(m, piece, promotion)| LegalMove::normal(position, m, piece, promotion))
+        .chain(legal_castling_moves(position))
+}
+
+/// The maximum number of legal moves suggested when a move is illegal.
+const MAX_SUGGESTIONS: usize = 4;
+
+/// Returns the legal moves that are the closest to a move of a piece to a destination, from the
+/// closest to the farthest.
+///
+/// Moves of the same kind of piece to the destination come first, followed by moves of other
+/// pieces to the destination, and moves of the same kind of piece to a neighboring square, which
+/// are likely typos. Other moves are not considered close.
+fn closest_legal_moves(
+    position: &Position,
+    piece: PieceKind,
+    destination: Square,
+) -> Vec<LegalMove> {
+    let distance = |square: Square| {
+        let files = square.file().index().abs_diff(destination.file().index());
+        let ranks = square.rank().index().abs_diff(destination.rank().index());
+        files.max(ranks)
+    };
+    let mut moves = legal_moves(position)
+        .into_iter()
+        .filter_map(|m| {
+            let same_piece = position
+                .at(m.movement.from())
+                .is(Piece::new(position.active, piece));
+            let closeness = match (same_piece, distance(m.movement.to())) {
+                (true, 0) => 0,
+                (false, 0) => 1,
+                (true, 1) => 2,
+                _ => None?,
+            };
+            Some((closeness, m))
+        })
+        .collect::<Vec<_>>();
+    moves.sort_by_key(|(closeness, _)| *closeness);
+    moves
+        .into_iter()
+        .map(|(_, m)| m)
+        .take(MAX_SUGGESTIONS)
+        .collect()
+}
+
+/// Lists moves as alternatives, in canonical SAN (e.g., "Nbd2, Nfd2 or Nf3").
+fn join_alternatives(moves: &[LegalMove]) -> String {
+    let sans = moves
+        .iter()
+        .map(|m| m.san().to_string())
+        .collect::<Vec<_>>();
+    match sans.split_last() {
+        None => String::new(),
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {last}", rest.join(", ")),
+    }
+}
+
+/// Returns all the legal moves in a position, including castling moves.
+pub fn legal_moves(position: &Position) -> Vec<LegalMove> {
+    let mut moves = iter_legal_moves(position).collect::<Vec<_>>();
+    disambiguate(&mut moves);
+    moves
+}
+
+/// Tests whether the active player can legally capture a pawn en passant in a position.
+pub fn can_capture_en_passant(position: &Position) -> bool {
+    let mut scratch = position.clone();
+    position.en_passant_target_file.is_some()
+        && valid_moves(position, PieceKind::Pawn)
+            .into_iter()
+            .filter(|m| m.en_passant_metadata.en_passant_capture_file().is_some())
+            .any(|m| m.is_legal(&mut scratch, PieceKind::Pawn))
+}
+
+/// Tests whether the active player has at least one legal move in a position.
+pub fn has_legal_move(position: &Position) -> bool {
+    legal_normal_moves(position).next().is_some() || legal_castling_moves(position).next().is_some()
+}
+
+/// The maximum depth for [`perft`] and [`divide`].
+///
+/// The number of positions grows exponentially with the depth: from the starting position, there
+/// are almost 5 million positions at depth 5, and 119 million at depth 6.
+pub const MAX_PERFT_DEPTH: u32 = 5;
+
+/// Counts the positions reached after exactly `depth` legal moves from a position.
+///
+/// Comparing this count with known results is the usual way to test move generation.
+pub fn perft(position: &Position, depth: u32) -> u64 {
+    count_leaves(&mut position.clone(), depth)
+}
+
+/// Counts the positions reached after exactly `depth` legal moves from a position, by making and
+/// taking back moves in place. The position is left unchanged.
+fn count_leaves(position: &mut Position, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let normal_moves = legal_normal_moves(position).collect::<Vec<_>>();
+    // Castling moves are rare enough to be applied to new positions.
+    let castling_moves = legal_castling_moves(position).collect::<Vec<_>>();
+    if depth == 1 {
+        return (normal_moves.len() + castling_moves.len()) as u64;
+    }
+    let mut nodes = 0;
+    for (m, piece, promotion) in normal_moves {
+        let undo = m.make(position, piece, promotion);
+        nodes += count_leaves(position, depth - 1);
+        m.unmake(position, undo);
+    }
+    for mut m in castling_moves {
+        nodes += count_leaves(&mut m.position, depth - 1);
+    }
+    nodes
+}
+
+/// Returns each legal move in a position, together with the number of positions reached after
+/// exactly `depth` legal moves starting with that move (see [`perft`]).
+///
+/// There is no move at depth 0.
+pub fn divide(position: &Position, depth: u32) -> Vec<(LegalMove, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    legal_moves(position)
+        .into_iter()
+        .map(|mut m| {
+            let nodes = count_leaves(&mut m.position, depth - 1);
+            (m, nodes)
+        })
+        .collect()
+}
+
 pub fn parse_turn(s: &str) -> crate::Result<AnnotatedAlgebraicTurn> {
     s.parse()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::parse_fen;
+
+    /// Checks the perft results of a position, from depth 1.
+    fn assert_perft(fen: &str, expected: &[u64]) {
+        let position = parse_fen(fen).unwrap();
+        for (depth, &nodes) in (1..).zip(expected) {
+            assert_eq!(perft(&position, depth), nodes, "{fen} at depth {depth}");
+        }
+    }
+
+    #[test]
+    fn starting_position() {
+        assert_perft(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[20, 400, 8902, 197281],
+        );
+    }
+
+    #[test]
+    fn kiwipete() {
+        assert_perft(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97862],
+        );
+    }
+
+    #[test]
+    fn en_passant() {
+        // Rook endgame with en passant captures that expose the king along the rank.
+        assert_perft(
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43238],
+        );
+        // En passant captures that would leave the king in check.
+        assert_perft("8/5bk1/8/2Pp4/8/1K6/8/8 w - d6 0 1", &[8, 104, 736, 9287]);
+        assert_perft(
+            "8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1",
+            &[15, 126, 1928, 13931],
+        );
+    }
+
+    #[test]
+    fn promotions() {
+        assert_perft(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467],
+        );
+        assert_perft(
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            &[6, 264, 9467],
+        );
+        assert_perft(
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[44, 1486, 62379],
+        );
+        // Promotion out of check, and underpromotions.
+        assert_perft("2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1", &[11, 133, 1442, 19174]);
+        assert_perft("8/P1k5/K7/8/8/8/8/8 w - - 0 1", &[6, 27, 273, 1329]);
+    }
+
+    #[test]
+    fn castling() {
+        assert_perft(
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            &[46, 2079, 89890],
+        );
+        // Castling through attacked squares, and castling that gives check.
+        assert_perft(
+            "r3k2r/8/3Q4/8/8/5q2/8/R3K2R b KQkq - 0 1",
+            &[44, 1494, 50509],
+        );
+        assert_perft("5k2/8/8/8/8/8/8/4K2R w K - 0 1", &[15, 66, 1198, 6399]);
+        // Chess960 castling, with the king and a rook next to each other.
+        assert_perft(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            &[21, 528, 12189],
+        );
+    }
+
+    #[test]
+    fn divide_counts() {
+        let position =
+            parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let moves = divide(&position, 3);
+        assert_eq!(moves.len(), 20);
+        assert_eq!(moves.iter().map(|(_, nodes)| nodes).sum::<u64>(), 8902);
+        let (_, nodes) = moves
+            .iter()
+            .find(|(m, _)| m.san().to_string() == "e4")
+            .unwrap();
+        assert_eq!(*nodes, 600);
+        assert!(divide(&position, 0).is_empty());
+    }
+}
@@ -0,0 +1,298 @@
+use crate::error::Error;
+use crate::json::Json;
+use std::str::FromStr;
+
+/// The tag pairs of a PGN game, in the order they appear in.
+#[derive(Debug, Clone, Default)]
+pub struct Tags(Vec<(String, String)>);
+
+impl Tags {
+    /// Returns the value of a tag.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns an iterator over the `(name, value)` pairs, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Tests whether the tags meet some criteria.
+    ///
+    /// Each criterion is a `(names, values)` pair. It is met if one of the tags in `names` has one
+    /// of the values in `values`. The tags meet the criteria if they meet all of them.
+    pub fn matches(&self, criteria: &[(Vec<&str>, Vec<&str>)]) -> bool {
+        criteria.iter().all(|(names, values)| {
+            names
+                .iter()
+                .filter_map(|name| self.get(name))
+                .any(|value| values.contains(&value))
+        })
+    }
+
+    /// Adds a tag pair at the end.
+    ///
+    /// Fails if the tag is already defined.
+    pub fn insert(&mut self, name: &str, value: String) -> crate::Result<()> {
+        if self.get(name).is_some() {
+            Err(format!("invalid PGN: \"{}\" key defined twice", name))?
+        }
+        self.0.push((name.to_string(), value));
+        Ok(())
+    }
+
+    /// Tests whether the `Variant` tag indicates a game of Chess960 (also known as Fischer Random
+    /// Chess).
+    pub fn is_chess960(&self) -> bool {
+        self.get("Variant").is_some_and(|variant| {
+            let variant = variant
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+                .to_ascii_lowercase();
+            matches!(
+                variant.as_str(),
+                "chess960" | "960" | "fischerandom" | "fischerrandom" | "fischerrandomchess"
+            )
+        })
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::Object(
+            self.0
+                .iter()
+                .map(|(name, value)| (name.clone(), value.as_str().into()))
+                .collect(),
+        )
+    }
+
+    /// Converts the values of well-known tags to structured values.
+    ///
+    /// Tags that are not well-known are left out. Values that are unknown or malformed are
+    /// converted to `null`.
+    pub fn to_parsed_json(&self) -> Json {
+        Json::Object(
+            self.0
+                .iter()
+                .filter_map(|(name, value)| {
+                    let parsed = match name.as_str() {
+                        "Date" | "EventDate" | "UTCDate" => {
+                            value.parse::<Date>().ok().map(Date::to_json).into()
+                        }
+                        "Round" => parse_round(value).into(),
+                        "WhiteElo" | "BlackElo" => value.parse::<u32>().ok().into(),
+                        "TimeControl" => value
+                            .parse::<TimeControl>()
+                            .ok()
+                            .map(|time_control| time_control.to_json())
+                            .into(),
+                        _ => None?,
+                    };
+                    Some((name.clone(), parsed))
+                })
+                .collect(),
+        )
+    }
+}
+
+/// A date, as found in the `Date` tag.
+///
+/// Unknown parts are `None`.
+#[derive(Debug, Copy, Clone)]
+pub struct Date {
+    year: Option<u32>,
+    month: Option<u32>,
+    day: Option<u32>,
+}
+
+impl Date {
+    pub fn to_json(self) -> Json {
+        Json::object([
+            ("year", self.year.into()),
+            ("month", self.month.into()),
+            ("day", self.day.into()),
+        ])
+    }
+}
+
+impl FromStr for Date {
+    type Err = Error;
+
+    /// Parses a date.
+    ///
+    /// [The specification] defines the format of dates in section 8.1.1.3:
+    ///
+    /// > The Date tag value gives the starting date for the game.  (Note: this is not
+    /// > necessarily the same as the starting date for the event.)  The date is given
+    /// > with respect to the local time of the site given in the Event tag.  The Date tag
+    /// > value field always uses a standard ten character format: "YYYY.MM.DD".  The
+    /// > first four characters are digits that give the year, the next character is a
+    /// > period, the next two characters are digits that give the month, the next
+    /// > character is a period, and the final two characters are digits that give the
+    /// > day of the month.  If the any of the digit fields are not known, then question
+    /// > marks are used in place of the digits.
+    ///
+    /// [the specification]: https://ia902908.us.archive.org/26/items/pgn-standard-1994-03-12/PGN_standard_1994-03-12.txt
+    fn from_str(s: &str) -> crate::Result<Self> {
+        let invalid = || format!("invalid date: {:?}", s);
+        let parse_part = |part: &str, length: usize, range: std::ops::RangeInclusive<u32>| {
+            if part.len() != length {
+                Err(invalid())
+            } else if part.chars().all(|c| c == '?') {
+                Ok(None)
+            } else if part.chars().all(|c| c.is_ascii_digit()) {
+                // `part` only contains digits, so it can only fail to parse if it overflows.
+                let n = part.parse().map_err(|_| invalid())?;
+                range.contains(&n).then_some(Some(n)).ok_or_else(invalid)
+            } else {
+                Err(invalid())
+            }
+        };
+        let [year, month, day] = s.split('.').collect::<Vec<_>>()[..] else {
+            Err(invalid())?
+        };
+        Ok(Self {
+            year: parse_part(year, 4, 0..=9999)?,
+            month: parse_part(month, 2, 1..=12)?,
+            day: parse_part(day, 2, 1..=31)?,
+        })
+    }
+}
+
+/// Parses the value of a `Round` tag.
+///
+/// Returns the successive round numbers (e.g., `[3, 1]` for `"3.1"`), or `None` if the round is
+/// unknown (`"?"`), not applicable (`"-"`) or malformed.
+fn parse_round(value: &str) -> Option<Vec<u32>> {
+    value.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// A period of a time control.
+#[derive(Debug, Copy, Clone)]
+pub struct TimeControlPeriod {
+    /// The number of moves each player must play during this period, or `None` if the period
+    /// lasts until the end of the game.
+    pub moves: Option<u32>,
+    /// The time given to each player for this period, in seconds.
+    pub seconds: u32,
+    /// The time added to the clock of a player after each of their moves, in seconds.
+    pub increment: u32,
+}
+
+impl TimeControlPeriod {
+    pub fn to_json(self) -> Json {
+        Json::object([
+            ("moves", self.moves.into()),
+            ("seconds", self.seconds.into()),
+            ("increment", self.increment.into()),
+        ])
+    }
+}
+
+/// A time control, as found in the `TimeControl` tag.
+///
+/// If the last period has a number of moves, it is repeated until the end of the game.
+#[derive(Debug, Clone)]
+pub struct TimeControl {
+    periods: Vec<TimeControlPeriod>,
+}
+
+impl TimeControl {
+    /// Returns the time given to each player at the start of the game, in seconds.
+    pub fn initial_time(&self) -> u32 {
+        // There is always at least one period.
+        self.periods[0].seconds
+    }
+
+    /// Returns the time added to the clock of a player after their `n`-th move, in seconds.
+    ///
+    /// This is the increment of the period the move belongs to, plus the time given for the next
+    /// period if the move is the last one of its period.
+    pub fn time_added(&self, n: u32) -> u32 {
+        let mut index = 0;
+        let mut first = 1u32;
+        loop {
+            let period = self.periods[index];
+            let Some(moves) = period.moves else {
+                return period.increment;
+            };
+            let last = first.saturating_add(moves - 1);
+            if n <= last {
+                let next = self.periods.get(index + 1).unwrap_or(&period);
+                return period.increment + if n == last { next.seconds } else { 0 };
+            }
+            first = last.saturating_add(1);
+            index = (index + 1).min(self.periods.len() - 1);
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::array(self.periods.iter().map(|period| period.to_json()))
+    }
+}
+
+impl FromStr for TimeControl {
+    type Err = Error;
+
+    /// Parses a time control.
+    ///
+    /// [The specification] defines the format of time controls in section 9.6.1:
+    ///
+    /// > The third field kind is composed of two positive integers separated by a
+    /// > solidus ("/") character.  The first integer is the number of moves in the
+    /// > period and the second is the number of seconds in the period.  Thus, a time
+    /// > control period of 40 moves in 2 1/2 hours would be represented as "40/9000".
+    /// >
+    /// > The fourth field kind is used for a "sudden death" control period.  It should
+    /// > only be used for the last period in a time control.  It is composed of a single
+    /// > integer that gives the number of seconds for the period.  Thus, a blitz control
+    /// > period for the whole game would be represented as "300".
+    /// >
+    /// > The fifth field kind is used for an "incremental" control period.  It should
+    /// > only be used for the last period in a time control.  It is composed of two
+    /// > positive integers separated by a plus sign ("+") character.  The first integer
+    /// > gives the minimum number of seconds allocated for the period and the second
+    /// > integer gives the number of extra seconds added after each move is made.  So,
+    /// > an incremental time control of 90 minutes plus one extra minute per move would
+    /// > be given by "4500+60" in the TimeControl tag value.
+    ///
+    /// Periods are separated by colons (`:`). Unknown (`?`), absent (`-`) and sandclock (`*`)
+    /// time controls are not supported. Increments are also allowed in periods with a number of
+    /// moves (e.g., `40/5400+30`), as is common in practice.
+    ///
+    /// [the specification]: https://ia902908.us.archive.org/26/items/pgn-standard-1994-03-12/PGN_standard_1994-03-12.txt
+    fn from_str(s: &str) -> crate::Result<Self> {
+        let invalid = || format!("invalid time control: {:?}", s);
+        let periods = s
+            .split(':')
+            .map(|period| {
+                let (moves, period) = match period.split_once('/') {
+                    Some((moves, period)) => (Some(moves), period),
+                    None => (None, period),
+                };
+                let (seconds, increment) = period.split_once('+').unwrap_or((period, "0"));
+                Ok(TimeControlPeriod {
+                    moves: moves
+                        .map(|moves| moves.parse().ok().filter(|&moves| moves > 0))
+                        .map(|moves| moves.ok_or_else(invalid))
+                        .transpose()?,
+                    seconds: seconds.parse().map_err(|_| invalid())?,
+                    increment: increment.parse().map_err(|_| invalid())?,
+                })
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+        // Only the last period can last until the end of the game.
+        if periods[..periods.len() - 1]
+            .iter()
+            .any(|period| period.moves.is_none())
+        {
+            Err(invalid())?
+        }
+        Ok(Self { periods })
+    }
+}
@@ -0,0 +1,133 @@
+use crate::model::{Color, PieceKind, Position, Square, SquareContent};
+use crate::san::{has_legal_move, is_in_check};
+use crate::utils::Name;
+use crate::zobrist::{is_same_position, zobrist_hash};
+
+/// A reason for a game to be over, or for a player to be able to claim a draw.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Termination {
+    /// The active player is in check and has no legal move.
+    Checkmate,
+    /// The active player is not in check and has no legal move.
+    Stalemate,
+    /// Neither player has enough material left to checkmate.
+    InsufficientMaterial,
+    /// Seventy-five consecutive moves were made by each player without any capture or pawn move.
+    SeventyFiveMoveRule,
+    /// The same position appeared five times.
+    FivefoldRepetition,
+    /// Fifty consecutive moves were made by each player without any capture or pawn move. A draw
+    /// can be claimed.
+    FiftyMoveRule,
+    /// The same position appeared three times. A draw can be claimed.
+    ThreefoldRepetition,
+}
+
+impl Termination {
+    /// Returns the result of a game that ended this way in a position, as a game termination
+    /// marker.
+    ///
+    /// Returns `None` if the game is not over, i.e., if a draw can only be claimed.
+    pub fn result(self, position: &Position) -> Option<&'static str> {
+        match self {
+            Self::Checkmate => match position.active {
+                Color::White => Some("0-1"),
+                Color::Black => Some("1-0"),
+            },
+            Self::Stalemate
+            | Self::InsufficientMaterial
+            | Self::SeventyFiveMoveRule
+            | Self::FivefoldRepetition => Some("1/2-1/2"),
+            Self::FiftyMoveRule | Self::ThreefoldRepetition => None,
+        }
+    }
+}
+
+impl Name for Termination {
+    fn name(&self) -> String {
+        match self {
+            Self::Checkmate => "checkmate".into(),
+            Self::Stalemate => "stalemate".into(),
+            Self::InsufficientMaterial => "insufficient-material".into(),
+            Self::SeventyFiveMoveRule => "seventy-five-move-rule".into(),
+            Self::FivefoldRepetition => "fivefold-repetition".into(),
+            Self::FiftyMoveRule => "fifty-move-rule".into(),
+            Self::ThreefoldRepetition => "threefold-repetition".into(),
+        }
+    }
+}
+
+/// Tests whether neither player can possibly checkmate the other in a position.
+///
+/// This only detects the usual cases: king against king, king and a single minor piece against
+/// king, and positions where all the remaining pieces besides the kings are bishops standing on
+/// squares of the same color.
+fn is_insufficient_material(position: &Position) -> bool {
+    let pieces = Square::all()
+        .filter_map(|square| match position.at(square) {
+            SquareContent::Empty => None,
+            SquareContent::Piece(piece) => Some((square, piece)),
+        })
+        .filter(|(_, piece)| piece.kind != PieceKind::King)
+        .collect::<Vec<_>>();
+    let is_light = |square: Square| (square.file().index() + square.rank().index()) % 2 == 1;
+    match pieces[..] {
+        [] => true,
+        [(_, piece)] => matches!(piece.kind, PieceKind::Knight | PieceKind::Bishop),
+        [(first, _), ..] => pieces.iter().all(|(square, piece)| {
+            piece.kind == PieceKind::Bishop && is_light(*square) == is_light(first)
+        }),
+    }
+}
+
+/// Counts how many times the last position of a sequence appears in that sequence, including the
+/// positions that precede it.
+///
+/// Two positions are considered the same if the same pieces occupy the same squares, the same
+/// player is to move, and the same moves are possible (including castling and en passant).
+fn repetition_count(previous_positions: &[Position], positions: &[Position]) -> usize {
+    let Some(last) = positions.last() else {
+        return 0;
+    };
+    // Hashes are stored in positions, so comparing them first is much cheaper.
+    previous_positions
+        .iter()
+        .chain(positions)
+        .filter(|position| {
+            zobrist_hash(position) == zobrist_hash(last) && is_same_position(position, last)
+        })
+        .count()
+}
+
+/// Determines how a game whose successive positions are given ended, if it did.
+///
+/// `previous_positions` are the positions of the game before `positions` (e.g., before a
+/// variation), which only matter for repetitions. The move counters used by the fifty-move and
+/// seventy-five-move rules are already part of the positions.
+///
+/// Game endings are reported in priority order: a checkmate takes precedence over every draw, and
+/// draws that end the game automatically take precedence over draws that can only be claimed.
+pub fn termination(previous_positions: &[Position], positions: &[Position]) -> Option<Termination> {
+    let position = positions.last()?;
+    if !has_legal_move(position) {
+        return Some(if is_in_check(position) {
+            Termination::Checkmate
+        } else {
+            Termination::Stalemate
+        });
+    }
+    let repetitions = repetition_count(previous_positions, positions);
+    if is_insufficient_material(position) {
+        Some(Termination::InsufficientMaterial)
+    } else if position.halfmove >= 150 {
+        Some(Termination::SeventyFiveMoveRule)
+    } else if repetitions >= 5 {
+        Some(Termination::FivefoldRepetition)
+    } else if position.halfmove >= 100 {
+        Some(Termination::FiftyMoveRule)
+    } else if repetitions >= 3 {
+        Some(Termination::ThreefoldRepetition)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,201 @@
+use crate::model::{Color, File, Piece, Position, Side, Square};
+use crate::san::can_capture_en_passant;
+
+/// Returns the next number of a SplitMix64 sequence, and the next state of the generator.
+const fn split_mix(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31), state)
+}
+
+/// Returns `N` pseudorandom numbers, generated from a seed.
+const fn keys<const N: usize>(seed: u64) -> [u64; N] {
+    let mut keys = [0; N];
+    let mut state = seed;
+    let mut i = 0;
+    while i < N {
+        (keys[i], state) = split_mix(state);
+        i += 1;
+    }
+    keys
+}
+
+/// The keys of the pieces, indexed by `64 * (6 * color + kind) + square`.
+static PIECE_KEYS: [u64; 768] = keys(1);
+
+/// The keys of the castling availabilities, indexed by `8 * (2 * color + side) + rook file`.
+static CASTLING_KEYS: [u64; 32] = keys(2);
+
+/// The keys of the en passant target squares, indexed by file.
+static EN_PASSANT_KEYS: [u64; 8] = keys(3);
+
+/// The key added when Black is to move.
+static BLACK_TO_MOVE_KEY: u64 = keys::<1>(4)[0];
+
+/// Returns the key of a piece standing on a square.
+///
+/// The Zobrist hash of a board is the exclusive or of the keys of all its pieces, so that it can be
+/// updated when a piece is added or removed.
+pub fn piece_key(piece: Piece, square: Square) -> u64 {
+    PIECE_KEYS[64 * (6 * piece.color.index() + piece.kind.index()) + square.index()]
+}
+
+fn castling_key(color: Color, side: Side, file: File) -> u64 {
+    let side = match side {
+        Side::King => 0,
+        Side::Queen => 1,
+    };
+    CASTLING_KEYS[8 * (2 * color.index() + side) + file.index()]
+}
+
+/// Returns the file on which an en passant capture is legal in a position, if any.
+pub fn en_passant_file(position: &Position) -> Option<File> {
+    position
+        .en_passant_target_file
+        .filter(|_| can_capture_en_passant(position))
+}
+
+/// Returns the Zobrist hash of a position.
+///
+/// It depends on the same things as [`is_same_position`]: two positions that are the same have the
+/// same hash, and two positions that are not are very unlikely to. It is stored in the position,
+/// and updated as moves are made.
+pub fn zobrist_hash(position: &Position) -> u64 {
+    position.zobrist
+}
+
+/// Recomputes the Zobrist hash stored in a position, after some of its fields were modified
+/// directly.
+pub fn rehash(position: &mut Position) {
+    position.zobrist = compute_hash(position, en_passant_file(position))
+}
+
+/// Computes the Zobrist hash of a position from the hash of its board, given the file on which an
+/// en passant capture is legal, if any (see [`en_passant_file`]).
+///
+/// The other keys are few enough to be combined again after each move.
+pub fn compute_hash(position: &Position, en_passant_file: Option<File>) -> u64 {
+    let mut hash = position.board.zobrist();
+    if position.active == Color::Black {
+        hash ^= BLACK_TO_MOVE_KEY
+    }
+    for color in [Color::White, Color::Black] {
+        for side in [Side::King, Side::Queen] {
+            if let Some(file) = position.castling_availabilities.get(color, side) {
+                hash ^= castling_key(color, side, file)
+            }
+        }
+    }
+    if let Some(file) = en_passant_file {
+        hash ^= EN_PASSANT_KEYS[file.index()]
+    }
+    hash
+}
+
+/// Tests whether two positions are the same.
+///
+/// Two positions are the same if the same pieces occupy the same squares, the same player is to
+/// move, and the same moves are possible (including castling and en passant). The move counters
+/// are ignored.
+pub fn is_same_position(position: &Position, other: &Position) -> bool {
+    position.board == other.board
+        && position.active == other.active
+        && position.castling_availabilities == other.castling_availabilities
+        && en_passant_file(position) == en_passant_file(other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::{fen, parse_fen};
+    use crate::san::parse_turn;
+
+    /// Plays turns from a position given in FEN, and returns the final position.
+    fn play(fen: &str, turns: &str) -> Position {
+        let mut position = parse_fen(fen).unwrap();
+        for (index, turn) in turns.split_whitespace().enumerate() {
+            position = parse_turn(turn)
+                .unwrap()
+                .apply(index, &position)
+                .unwrap()
+                .position;
+        }
+        position
+    }
+
+    const STARTING_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[test]
+    fn incremental_updates() {
+        // Captures, en passant captures, promotions and castling all update the hash of the board.
+        let position = play(
+            STARTING_POSITION,
+            "e4 d5 e5 f5 exf6 Nh6 fxg7 Bf5 gxh8=Q Qd7 Nf3 Nc6 Bb5 O-O-O O-O",
+        );
+        let rebuilt = parse_fen(&fen(position.clone())).unwrap();
+        assert_eq!(position.board.zobrist(), rebuilt.board.zobrist());
+        assert_eq!(zobrist_hash(&position), zobrist_hash(&rebuilt));
+        assert!(is_same_position(&position, &rebuilt));
+        // So do legal en passant target squares.
+        let en_passant = play(STARTING_POSITION, "e4 d5 e5 f5");
+        let rebuilt = parse_fen(&fen(en_passant.clone())).unwrap();
+        assert_eq!(zobrist_hash(&en_passant), zobrist_hash(&rebuilt));
+        assert_ne!(
+            zobrist_hash(&en_passant),
+            zobrist_hash(&play(STARTING_POSITION, "e4 f5 e5 d5"))
+        );
+        // So do inverting positions.
+        let inverted = position.invert();
+        let rebuilt = parse_fen(&fen(inverted.clone())).unwrap();
+        assert_eq!(inverted.board.zobrist(), rebuilt.board.zobrist());
+    }
+
+    #[test]
+    fn transpositions() {
+        let first = play(STARTING_POSITION, "e4 e5 Nf3 Nc6");
+        let second = play(STARTING_POSITION, "Nf3 Nc6 e4 e5");
+        assert_eq!(zobrist_hash(&first), zobrist_hash(&second));
+        assert!(is_same_position(&first, &second));
+        // The move counters are ignored.
+        let starting_position = parse_fen(STARTING_POSITION).unwrap();
+        let back = play(STARTING_POSITION, "Nf3 Nc6 Ng1 Nb8");
+        assert_eq!(zobrist_hash(&back), zobrist_hash(&starting_position));
+        assert!(is_same_position(&back, &starting_position));
+    }
+
+    #[test]
+    fn different_positions() {
+        let starting_position = parse_fen(STARTING_POSITION).unwrap();
+        let different = [
+            // The player to move.
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1",
+            // The castling availabilities.
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w Qkq - 0 1",
+            // The pieces.
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1",
+        ];
+        for fen in different {
+            let position = parse_fen(fen).unwrap();
+            assert_ne!(zobrist_hash(&position), zobrist_hash(&starting_position));
+            assert!(!is_same_position(&position, &starting_position));
+        }
+    }
+
+    #[test]
+    fn en_passant() {
+        // No black pawn can capture en passant, so the target square does not matter.
+        let after_e4 =
+            parse_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+        let without =
+            parse_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        assert_eq!(zobrist_hash(&after_e4), zobrist_hash(&without));
+        assert!(is_same_position(&after_e4, &without));
+        // Here, the pawn on d4 can capture en passant.
+        let after_e3 = parse_fen("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1").unwrap();
+        let without = parse_fen("4k3/8/8/8/3pP3/8/8/4K3 b - - 0 1").unwrap();
+        assert_ne!(zobrist_hash(&after_e3), zobrist_hash(&without));
+        assert!(!is_same_position(&after_e3, &without));
+    }
+}
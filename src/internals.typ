@@ -7,30 +7,154 @@
   )
 }
 
-#let deserialize-game(game) = {
-  let (positions, moves) = array(game).split(0xff)
+#let shredder-fen(position) = {
+  str(functions.shredder_fen(bytes(position.fen)))
+}
+
+#let position-hash(position) = {
+  str(functions.position_hash(bytes(position.fen)))
+}
+
+#let same-position(position, other) = {
+  json(functions.same_position(bytes(position.fen), bytes(other.fen)))
+}
+
+#let canonicalize-position(position, reset-move-counters) = {
+  let result = json(functions.canonicalize_position(
+    bytes(position.fen),
+    bytes(if reset-move-counters { "1" } else { "" }),
+  ))
   (
-    positions: positions.split(0).map(position => (
+    position: (
       type: <board-n-pieces:fen>,
-      fen: str(bytes(position))
-    )),
-    moves: moves.split(0).map(move => str(bytes(move)).split()),
+      fen: result.fen,
+    ),
+    changes: result.changes,
   )
 }
 
-#let replay-game(starting-position, turns) = {
+#let deserialize-line(line) = (
+  positions: line.positions.map(fen => (
+    type: <board-n-pieces:fen>,
+    fen: fen,
+  )),
+  moves: line.moves,
+  san: line.san,
+  check-marks: line.check-marks,
+  nags: line.nags,
+  comments: line.comments,
+  marked-squares: line.marked-squares,
+  arrows: line.arrows,
+  clocks: line.clocks,
+  elapsed-times: line.elapsed-times,
+  evaluations: line.evaluations,
+  mates: line.mates,
+  termination: line.termination,
+  variations: line.variations.map(variations => variations.map(deserialize-line)),
+)
+
+#let deserialize-game(game) = deserialize-line(json(game))
+
+#let replay-game(starting-position, turns, chess960) = {
   let game = functions.replay_game(
     bytes(starting-position.fen),
-    turns.map(bytes).join(bytes((0, )))
+    turns.map(bytes).join(bytes((0, ))),
+    bytes(if chess960 { "1" } else { "" }),
   )
   deserialize-game(game)
 }
 
-#let game-from-pgn(pgn) = {
-  let game = functions.game_from_pgn(
+#let deserialize-pgn-game(game) = (
+  ..deserialize-line(game),
+  time-spent: game.time-spent,
+  tags: game.tags,
+  parsed-tags: game.parsed-tags,
+)
+
+#let game-from-pgn(pgn, strict) = {
+  let game = json(functions.game_from_pgn(
+    bytes(pgn),
+    bytes(if strict { "1" } else { "" }),
+  ))
+  if strict {
+    (..deserialize-pgn-game(game), warnings: game.warnings)
+  } else {
+    deserialize-pgn-game(game)
+  }
+}
+
+#let serialize-strings(strings) = {
+  if strings.len() == 0 {
+    return bytes(())
+  }
+  strings.map(bytes).join(bytes((0, )))
+}
+
+#let games-from-pgn(pgn, indices, tags, player) = {
+  let games = json(functions.games_from_pgn(
     bytes(pgn),
+    serialize-strings(indices.map(str)),
+    serialize-strings(tags.pairs().map(((name, values)) => {
+      if type(values) == str {
+        values = (values, )
+      }
+      values.map(value => (name, value))
+    }).flatten()),
+    bytes(if player == none { "" } else { player }),
+  ))
+  games.map(game => {
+    if game.error == none {
+      (..deserialize-pgn-game(game), index: game.index, error: none)
+    } else {
+      game
+    }
+  })
+}
+
+#let normalize-pgn(pgn) = {
+  str(functions.normalize_pgn(bytes(pgn)))
+}
+
+#let export-game(starting-position, turns, tags) = {
+  str(functions.export_game(
+    bytes(starting-position.fen),
+    serialize-strings(turns),
+    serialize-strings(tags.pairs().flatten()),
+  ))
+}
+
+#let legal-moves(position, square) = {
+  let moves = functions.legal_moves(
+    bytes(position.fen),
+    bytes(if square == none { "" } else { square }),
   )
-  deserialize-game(game)
+  if moves.len() == 0 {
+    return ()
+  }
+  array(moves).split(0).map(move => {
+    let (from, to, san) = str(bytes(move)).split()
+    (from: from, to: to, san: san)
+  })
+}
+
+#let attack-map(position, x-ray) = {
+  json(functions.attack_map(
+    bytes(position.fen),
+    bytes(if x-ray { "1" } else { "" }),
+  ))
+}
+
+#let perft(position, depth, divide) = {
+  let result = json(functions.perft(
+    bytes(position.fen),
+    bytes(str(depth)),
+    bytes(if divide { "1" } else { "" }),
+  ))
+  if divide {
+    result
+  } else {
+    result.nodes
+  }
 }
 
 /// Converts a `board-n-pieces:fen` to a `board-n-pieces:position`.
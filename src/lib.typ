@@ -91,6 +91,61 @@
 }
 
 
+/// Writes a position in Forsyth–Edwards Notation, using Shredder-FEN for
+/// castling availabilities.
+///
+/// Positions created with `fen` accept standard FEN, X-FEN, and Shredder-FEN
+/// castling availabilities, and write them in X-FEN, which is the same as
+/// standard FEN unless the rooks do not start in the corners. Shredder-FEN
+/// instead always gives the file of the rook (e.g., `HAha` for the starting
+/// position).
+#let shredder-fen(position) = {
+  import "internals.typ": shredder-fen
+  shredder-fen(position)
+}
+
+
+/// Returns the Zobrist hash of a position, as a string of 16 hexadecimal
+/// digits.
+///
+/// Two positions have the same hash if they are the same according to
+/// `same-position`, and are very unlikely to otherwise. This makes hashes
+/// suitable for detecting transpositions or indexing positions.
+#let position-hash(position) = {
+  import "internals.typ": position-hash
+  position-hash(position)
+}
+
+
+/// Tests whether two positions are the same: the same pieces are on the same
+/// squares, the same player is to move, and the castling availabilities and
+/// the possible en passant captures are the same. The move counters are
+/// ignored, and so are en passant target squares when no pawn can capture en
+/// passant.
+#let same-position(position, other) = {
+  import "internals.typ": same-position
+  same-position(position, other)
+}
+
+
+/// Rewrites a position so that equivalent positions have the same
+/// Forsyth–Edwards Notation.
+///
+/// The en passant target square is removed if no pawn can legally capture en
+/// passant, and castling availabilities are removed if the king or the rook
+/// has left its home square. If `reset-move-counters` is `true`, the halfmove
+/// clock is reset to 0 and the fullmove number to 1.
+///
+/// Returns a dictionary with the following keys:
+/// - `position`, the canonical position.
+/// - `changes`, an array of strings describing each change that was made
+///   (e.g., `"removed castling availability K (the rook is not on h1)"`).
+#let canonicalize-position(position, reset-move-counters: false) = {
+  import "internals.typ": canonicalize-position
+  canonicalize-position(position, reset-move-counters)
+}
+
+
 /// Creates a position using Forsyth–Edwards Notation.
 ///
 /// For example, this creates the starting position.
@@ -114,24 +169,249 @@
 /// - `positions`, an array containing the successive results of applying the
 ///   given turns.
 /// - `moves`, an array containing the moves that were performed.
+/// - `san`, an array containing the moves that were performed, in canonical
+///   standard algebraic notation.
+/// - `check-marks`, an array containing, for each move, the check (`"+"`) or
+///   checkmate (`"#"`) mark that was written in the input, or `none`. Unlike
+///   `san`, this reflects the input as is.
+/// - `nags`, an array containing, for each move, an array of its numeric
+///   annotation glyphs. Each glyph is a dictionary containing its number
+///   (`code`), the Unicode symbol it is usually printed as (`symbol`), and its
+///   meaning (`meaning`). The latter two can be `none`. Move suffix annotations
+///   (`!`, `?`, `!!`, `??`, `!?`, and `?!`) are converted to the equivalent
+///   glyphs (`$1` to `$6`).
+/// - `comments`, an array containing, for each position, an array of the
+///   comments about it. The comments about the first position are the ones
+///   preceding the first move, and the other ones are the comments following
+///   the move leading to the position. This is always an array of empty arrays
+///   for `play`.
+/// - `marked-squares` and `arrows`, arrays containing, for each position, the
+///   squares highlighted and the arrows drawn by `[%csl]` and `[%cal]` commands
+///   in its comments (e.g., `[%csl Gd4,Re5]` or `[%cal Ge2e4]`). Highlighted
+///   squares are dictionaries with `square` and `color` keys, and arrows are
+///   dictionaries with `from`, `to`, and `color` keys. Colors are one of `"G"`
+///   (green), `"R"` (red), `"Y"` (yellow), and `"B"` (blue). These commands
+///   are removed from the comments, unless they are invalid. This is always an
+///   array of empty arrays for `play`.
+/// - `clocks`, `elapsed-times`, `evaluations` and `mates`, arrays containing,
+///   for each position, a number given by a command in its comments, or `none`.
+///   They are respectively the remaining time of the player who just moved
+///   (`[%clk 0:02:59]`), the time the last move took (`[%emt 0:00:07]`), both
+///   in seconds, and the evaluation of the position (`[%eval +0.34]` or
+///   `[%eval #-3]`), either in pawns or as a number of moves to mate. Both are
+///   from White's point of view. These commands are removed from the comments,
+///   unless they are invalid. This is always an array of `none` for `play`.
+/// - `termination`, a string describing how the game ended, or `none` if the
+///   game is not over in the last position. This is one of `"checkmate"`,
+///   `"stalemate"`, `"insufficient-material"`, `"seventy-five-move-rule"`, and
+///   `"fivefold-repetition"`. It can also be `"fifty-move-rule"` or
+///   `"threefold-repetition"` if a draw can be claimed in the last position.
+/// - `variations`, an array containing, for each move, an array of alternative
+///   lines. Each line is a dictionary with the same structure as the result,
+///   whose first position is the position the corresponding move is played
+///   from. This is always an array of empty arrays for `play`.
 ///
 /// Turns can be specified as an array of strings using standard algebraic
 /// notation. Alternatively, you can also specify a single string containing
-/// whitespace-separated turns.
-#let play(starting-position: starting-position, turns) = {
+/// whitespace-separated turns. Long algebraic notation (e.g., `Ng1-f3`) and UCI
+/// notation (e.g., `g1f3` or `e7e8q`) are also supported. In these notations,
+/// castling is written as a king move (e.g., `e1g1`), or as a king move onto
+/// the rook in Chess960 (e.g., `e1h1`).
+///
+/// - chess960 (bool): Whether to use the castling rules of Chess960, where the
+///   king and the rooks can start on any file. When castling, the king still
+///   moves to the g-file or the c-file and the rook next to it, but either
+///   piece can stay where it is. This is always the case if the castling
+///   availabilities of the starting position are given as files (as in
+///   Shredder-FEN) and the king or the rook is not on its usual square.
+#let play(starting-position: starting-position, turns, chess960: false) = {
   import "internals.typ": replay-game
   if type(turns) == str {
     turns = turns.split()
   }
-  replay-game(starting-position, turns)
+  replay-game(starting-position, turns, chess960)
 }
 
 
 /// Computes the successive positions and moves of a game described using
 /// Portable Game Notation.
-#let pgn(pgn-string) = {
+///
+/// Returns a dictionary with the same structure as `play`. Comments are
+/// available under the `comments` key, numeric annotation glyphs under the
+/// `nags` key, and recursive annotation variations under the `variations` key.
+///
+/// The dictionary also contains the following keys:
+/// - `time-spent`, an array containing, for each move of the main line, the
+///   time it took in seconds, or `none` if it cannot be determined. This is
+///   given by `[%emt]` commands, or computed from `[%clk]` commands and the
+///   `TimeControl` tag.
+/// - `tags`, a dictionary containing the tag pairs of the game (e.g., `White`
+///   or `Date`), in the order they appear in.
+/// - `parsed-tags`, a dictionary containing the values of well-known tags,
+///   converted to structured values:
+///   - `Date`, `EventDate` and `UTCDate` are converted to dictionaries with
+///     `year`, `month` and `day` keys. Unknown parts (`??`) are `none`.
+///   - `Round` is converted to an array of integers (e.g., `(3, 1)` for
+///     `"3.1"`).
+///   - `WhiteElo` and `BlackElo` are converted to integers.
+///   - `TimeControl` is converted to an array of periods (e.g., two for
+///     `"40/5400+30:1800+30"`). Each period is a dictionary with `moves` (the
+///     number of moves of the period, or `none` for the rest of the game),
+///     `seconds` and `increment` keys.
+///
+///   Values that are unknown or malformed are `none`. Tags that are not
+///   present in the game are left out.
+///
+/// - strict (bool): Whether to check the game for inconsistencies that are
+///   otherwise accepted. If `true`, the dictionary also contains a `warnings`
+///   key: an array of dictionaries with a `message` key describing the problem
+///   and a `ply` key, which is the index of the halfmove it concerns (`0` for
+///   the first move of White, as for errors), or `none` if it concerns the
+///   whole game. A problem with the final position has the index of the
+///   halfmove that would follow it. The following problems are reported: missing tags of the Seven Tag
+///   Roster, a `Result` tag that does not match the game termination marker,
+///   check (`+`) and checkmate (`#`) marks that are missing or do not match
+///   the position, including in variations, captures written without `x`, and
+///   a game termination marker that does not match a final checkmate or draw.
+///
+/// Chess960 castling rules are used if the `Variant` tag is `"Chess960"` (or
+/// `"Fischer Random"`).
+#let pgn(pgn-string, strict: false) = {
   import "internals.typ": game-from-pgn
-  game-from-pgn(pgn-string)
+  game-from-pgn(pgn-string, strict)
+}
+
+
+/// Reads the games of a Portable Game Notation string containing any number
+/// of games, such as a tournament file.
+///
+/// Returns an array of dictionaries. Each dictionary has the same structure as
+/// the result of `pgn`, and the following additional keys:
+/// - `index`, the index of the game in the string, starting from 0.
+/// - `error`, `none` if the game could be read, or a string describing why it
+///   could not, including where the error is in the PGN string. In the latter
+///   case, the dictionary only contains the `index`, `error` and `tags` keys
+///   (`tags` is empty if the tag pairs could not be read), and reading resumes
+///   at the next game.
+///
+/// - index (none, int, array): The indices of the games to return. By default,
+///   all games are returned.
+/// - tags (dictionary): Tag values the games to return must have. Each value
+///   can be a string or an array of strings, in which case any of them
+///   matches. For example, `(Result: "1-0")` only returns the games that White
+///   won.
+/// - player (none, str): A player the games to return must involve, as either
+///   White or Black.
+#let pgn-games(pgn-string, index: none, tags: (:), player: none) = {
+  import "internals.typ": games-from-pgn
+  if type(index) == int {
+    index = (index, )
+  } else if index == none {
+    index = ()
+  } else if index == () {
+    return ()
+  }
+  games-from-pgn(pgn-string, index, tags, player)
+}
+
+
+/// Writes a game in the export format of Portable Game Notation.
+///
+/// The Seven Tag Roster (`Event`, `Site`, `Date`, `Round`, `White`, `Black`,
+/// and `Result`) is written first, with `"?"` for missing tags, followed by
+/// the other tags in alphabetical order. If the game does not start from the
+/// standard starting position, `SetUp` and `FEN` tags are added. Moves are
+/// written in canonical standard algebraic notation, and lines are wrapped at
+/// 80 characters.
+///
+/// If the `Result` tag is not given, the result is deduced from how the game
+/// ended, or `"*"` if it is not over.
+///
+/// - turns (str, array): The turns of the game, as accepted by `play`.
+/// - tags (dictionary): The tag pairs of the game (e.g., `(White: "Carlsen")`).
+///   Values cannot contain control characters, such as line breaks.
+#let export-pgn(turns, starting-position: starting-position, tags: (:)) = {
+  import "internals.typ": export-game
+  if type(turns) == str {
+    turns = turns.split()
+  }
+  export-game(starting-position, turns, tags)
+}
+
+
+/// Rewrites a Portable Game Notation string in the export format, as described
+/// for `export-pgn`.
+///
+/// Comments, numeric annotation glyphs, and recursive annotation variations
+/// are kept. Move suffix annotations (e.g., `!?`) are written as the
+/// equivalent numeric annotation glyphs (e.g., `$5`). Reading the result with
+/// `pgn` gives back the same game.
+#let normalize-pgn(pgn-string) = {
+  import "internals.typ": normalize-pgn
+  normalize-pgn(pgn-string)
+}
+
+
+/// Computes the legal moves in a position.
+///
+/// Returns an array of dictionaries with the following keys:
+/// - `from`, the departure square of the move.
+/// - `to`, the destination square of the move. For castling moves, this is
+///   the destination square of the king.
+/// - `san`, the move in standard algebraic notation.
+///
+/// If `square` is not `none`, only the moves of the piece on that square are
+/// returned.
+#let legal-moves(position, square: none) = {
+  import "internals.typ": legal-moves
+  legal-moves(position, square)
+}
+
+
+/// Computes which pieces attack each square of a position.
+///
+/// Returns a dictionary whose keys are the names of all the squares. Each value
+/// is a dictionary with the keys `white` and `black`, containing the squares of
+/// the white and black pieces that attack the square, respectively. A piece
+/// attacks a square whether it is empty or not, so the attackers of a square
+/// include the defenders of the piece standing on it. Pawns attack the squares
+/// they could capture on.
+///
+/// If `x-ray` is `true`, sliding pieces also attack through the pieces of the
+/// same player that slide in the same direction, such as a rook behind a queen
+/// on the same file.
+///
+/// The keys can be passed directly to `board`'s `marked-squares`. For example,
+/// this marks the squares White attacks more than Black:
+/// ```typ
+/// #let attacks = attack-map(position)
+/// #board(
+///   position,
+///   marked-squares: attacks.keys().filter(square => {
+///     attacks.at(square).white.len() > attacks.at(square).black.len()
+///   }),
+/// )
+/// ```
+#let attack-map(position, x-ray: false) = {
+  import "internals.typ": attack-map
+  attack-map(position, x-ray)
+}
+
+
+/// Counts the positions reached after exactly `depth` legal moves from a
+/// position, as is usually done to test move generation. The depth can be at
+/// most 5, as the number of positions grows quickly.
+///
+/// Returns an integer. If `divide` is `true`, returns a dictionary with the
+/// following keys instead:
+/// - `nodes`, the number of positions.
+/// - `moves`, an array containing, for each legal move, a dictionary with the
+///   same keys as in `legal-moves`, and `nodes`, the number of positions
+///   reached after that move.
+#let perft(position, depth, divide: false) = {
+  assert(depth >= 0, message: "the depth must not be negative")
+  import "internals.typ": perft
+  perft(position, depth, divide)
 }
 
 
@@ -168,7 +448,8 @@
   ///
   /// Must be an array of `(start, end)` pairs. Alternatively, you can pass an
   /// array of strings: for example, `("e2 e4", "e7 e5")` or, more compactly,
-  /// `("e2e4", "e7e5")`.
+  /// `("e2e4", "e7e5")`. To fill an arrow differently from the others, use a
+  /// `(start, end, fill)` triple instead.
   arrows: (),
 
   /// Whether to reverse the board and display it from Black's point of view
@@ -266,18 +547,23 @@
   )
 
   arrows = arrows.map(arrow => {
+    let fill = arrow-fill
     let (start, end) = if type(arrow) == str {
       if arrow.len() == 4 {
         (arrow.slice(0, 2), arrow.slice(2, 4))
       } else {
         arrow.split()
       }
+    } else if arrow.len() == 3 {
+      fill = arrow.at(2)
+      arrow.slice(0, 2)
     } else {
       arrow
     }
     (
       square-coordinates(start),
       square-coordinates(end),
+      fill,
     )
   })
 
@@ -338,7 +624,7 @@
     .rev()
     .flatten()
 
-  for ((start-file, start-rank), (end-file, end-rank)) in arrows {
+  for ((start-file, start-rank), (end-file, end-rank), fill) in arrows {
     if reverse {
       start-file = width - start-file - 1
       start-rank = height - start-rank - 1
@@ -364,7 +650,7 @@
           head-thickness,
           head-length,
           tip,
-          arrow-fill,
+          fill,
           calc.abs(end-file - start-file) == 2,
         )
       } else {
@@ -377,7 +663,7 @@
           head-thickness,
           head-length,
           tip,
-          arrow-fill,
+          fill,
         )
       }
     }
@@ -18,6 +18,53 @@
   "r1bq1r2/pp2n3/4N1Pk/3pPp2/1b1n2Q1/2N5/PP3PP1/R1B1K2R b KQ - 0 15",
 )
 
+// Test castling rules.
+#assert.eq(bnp.legal-moves(bnp.fen("4k3/8/8/8/8/8/4r3/R3K2R w KQ - 0 1")).filter(m => m.san.starts-with("O-O")), ())
+#assert.eq(bnp.legal-moves(bnp.fen("5rk1/8/8/8/8/8/8/4K2R w K - 0 1")).filter(m => m.san.starts-with("O-O")), ())
+#assert.eq(bnp.legal-moves(bnp.fen("3rk3/8/8/8/8/8/8/R3K3 w Q - 0 1")).filter(m => m.san.starts-with("O-O")), ())
+#assert.eq(bnp.play(starting-position: bnp.fen("1r2k3/8/8/8/8/8/8/R3K3 w Q - 0 1"), "O-O-O").positions.last().fen, "1r2k3/8/8/8/8/8/8/2KR4 b - - 1 1")
+#assert.eq(bnp.play(starting-position: bnp.fen("r3k3/8/8/8/8/8/8/R3K3 w Qq - 0 1"), "Rxa8+").positions.last().fen, "R3k3/8/8/8/8/8/8/4K3 b - - 0 1")
+#assert.eq(bnp.play(starting-position: bnp.fen("4k3/8/8/8/7R/8/8/R3K3 w Q - 0 1"), "Rh5").positions.last().fen, "4k3/8/8/7R/8/8/8/R3K3 b Q - 1 1")
+#assert.eq(bnp.legal-moves(bnp.fen("4k3/8/8/8/8/8/8/5K1R w KQkq - 0 1")).filter(m => m.san.starts-with("O-O")), ())
+#assert.eq(bnp.legal-moves(bnp.fen("r3k2r/8/8/8/8/8/8/R4K1R w KQkq - 0 1")).filter(m => m.san.starts-with("O-O")), ())
+#assert.eq(bnp.legal-moves(bnp.fen("4k3/8/8/8/8/8/8/4KR2 w K - 0 1")).filter(m => m.san.starts-with("O-O")), ())
+
+// Test Chess960 castling.
+#let chess960-position = bnp.fen("bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w KQkq - 0 1")
+#assert.eq(bnp.play(starting-position: chess960-position, "O-O", chess960: true).positions.last().fen, "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRRKN b kq - 1 1")
+#assert.eq(bnp.play(starting-position: chess960-position, "f1g1", chess960: true).san, ("O-O", ))
+#assert.eq(bnp.play(starting-position: bnp.fen("rk5r/8/8/8/8/8/8/R5KR w KQkq - 0 1"), "O-O O-O-O", chess960: true).positions.last().fen, "2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 2")
+#assert.eq(bnp.play(starting-position: bnp.fen("4k3/8/8/8/8/8/8/4KR2 w F - 0 1"), "O-O").positions.last().fen, "4k3/8/8/8/8/8/8/5RK1 b - - 1 1")
+#assert.eq(bnp.play("e4 e5 Nf3 Nc6 Bc4 Bc5 e1h1", chess960: true).san.last(), "O-O")
+#assert.eq(bnp.pgn("[Variant \"Chess960\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. e1h1 *").san.last(), "O-O")
+
+// Test captures and promotions.
+#assert.eq(bnp.play("e4 d5 e4d5 Qd5 Nc3 Qd2+ Kd2").positions.last().fen, "rnb1kbnr/ppp1pppp/8/8/8/2N5/PPPK1PPP/R1BQ1BNR b kq - 0 4")
+#assert.eq(bnp.play(starting-position: bnp.fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1"), "b8=N").positions.last().fen, "1N2k3/8/8/8/8/8/8/4K3 b - - 0 1")
+#assert.eq(bnp.play(starting-position: bnp.fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1"), "b7b8r").san, ("b8=R+", ))
+
+// Test canonical SAN.
+#assert.eq(
+  bnp.play("e4 e5 Ngf3 Nc6 Bc4 Nf6 0-0 Bc5 d4 Bxd4 Nxd4 Nxd4 Qxd4 d6 Qd5 Qe7 Qxf7 Qxf7 Bxf7 Kxf7").san.join(" "),
+  "e4 e5 Nf3 Nc6 Bc4 Nf6 O-O Bc5 d4 Bxd4 Nxd4 Nxd4 Qxd4 d6 Qd5 Qe7 Qxf7+ Qxf7 Bxf7+ Kxf7",
+)
+#assert.eq(bnp.play(starting-position: bnp.fen("k7/2P5/8/8/8/8/8/4K3 w - - 0 1"), "c8Q").san, ("c8=Q+",))
+#assert.eq(bnp.play(starting-position: bnp.fen("k7/8/8/1N3N2/8/1N6/8/4K3 w - - 0 1"), "Nf5d4").san, ("Nfd4",))
+
+// Test long algebraic and UCI notations.
+#assert.eq(
+  bnp.play("e2e4 e7-e5 Ng1-f3 b8c6 f1c4 g8f6 e1g1 f8c5 d2d4 c5xd4 Nf3xd4").positions.last().fen,
+  bnp.play("e4 e5 Nf3 Nc6 Bc4 Nf6 O-O Bc5 d4 Bxd4 Nxd4").positions.last().fen,
+)
+#assert.eq(bnp.play(starting-position: bnp.fen("k7/4P3/8/8/8/8/8/4K3 w - - 0 1"), "e7e8q").san, ("e8=Q+",))
+
+// Test game termination.
+#assert.eq(immortal-draw.termination, none)
+#assert.eq(bnp.play("e4 e5 Qh5 Nc6 Bc4 Nf6 Qxf7").termination, "checkmate")
+#assert.eq(bnp.play(starting-position: bnp.fen("k7/8/8/2Q5/8/8/8/4K3 w - - 0 1"), "Qb6").termination, "stalemate")
+#assert.eq(bnp.play(starting-position: bnp.fen("k7/8/8/8/8/8/8/4KB2 w - - 0 1"), "Bc4").termination, "insufficient-material")
+#assert.eq(bnp.play("Nf3 Nf6 Ng1 Ng8 Nf3 Nf6 Ng1 Ng8").termination, "threefold-repetition")
+
 
 #let test-pgn(file-name, expected-last-position) = {
   let g = bnp.pgn(read("assets/" + file-name))
@@ -28,7 +75,202 @@
 // https://lichess.org/4cCk7Gi5
 #test-pgn("lichess-4cCk7Gi5.pgn", "5r2/5k1p/1p2p3/p1p1P3/5P1b/1P2P2q/PB3RR1/5K2 w - - 1 44")
 
+// Test PGN variations.
+#{
+  let g = bnp.pgn("1. e4 (1. d4 d5 (1... Nf6 2. c4) 2. c4) 1... e5 2. Nf3 (2. f4 exf4) 2... Nc6 *")
+  assert.eq(g.san, ("e4", "e5", "Nf3", "Nc6"))
+  assert.eq(g.variations.at(0).at(0).san, ("d4", "d5", "c4"))
+  assert.eq(g.variations.at(0).at(0).variations.at(1).at(0).san, ("Nf6", "c4"))
+  assert.eq(g.variations.at(2).at(0).positions.first(), g.positions.at(2))
+  assert.eq(g.variations.at(1), ())
+}
+#assert.eq(
+  bnp.pgn("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 12\"]\n\n12... Kd7 13. e4 (13. e3 Ke6) *").variations.at(1).first().san,
+  ("e3", "Ke6"),
+)
+#{
+  // Repetitions in variations include the positions before the variation.
+  let g = bnp.pgn("1. Nf3 Nf6 2. Ng1 Ng8 3. Nf3 Nf6 4. Ng1 e5 (4... Ng8 5. Nf3 (5. e4)) *")
+  assert.eq(g.termination, none)
+  assert.eq(g.variations.at(7).at(0).termination, "threefold-repetition")
+  assert.eq(g.variations.at(7).at(0).variations.at(1).at(0).termination, none)
+}
+
+// Test PGN comments.
+#{
+  let g = bnp.pgn("{Intro} 1. e4 {Best by test} ; rest of line\n e5 2. Nf3 ({Alternatively} 2. f4 {gambit}) Nc6 *")
+  assert.eq(g.comments, (("Intro",), ("Best by test", "rest of line"), (), (), ()))
+  assert.eq(g.variations.at(2).first().comments, (("Alternatively",), ("gambit",)))
+}
+
+// Test PGN square highlights and arrows.
+#{
+  let g = bnp.pgn("{[%csl Gd4]} 1. e4 {Good [%cal Ge2e4,Rd7d5] idea} e5 {[%csl Ye5]} *")
+  assert.eq(g.comments, ((), ("Good idea",), ()))
+  assert.eq(g.marked-squares, (((square: "d4", color: "G"),), (), ((square: "e5", color: "Y"),)))
+  assert.eq(g.arrows.at(1), ((from: "e2", to: "e4", color: "G"), (from: "d7", to: "d5", color: "R")))
+  assert.eq(bnp.play("e4").arrows, ((), ()))
+  let g = bnp.pgn("1. e4 {[%csl Zz9]} e5 {[%cal Ge2] [%csl Gd4,Re9]} *")
+  assert.eq(g.comments, ((), ("[%csl Zz9]",), ("[%cal Ge2] [%csl Gd4,Re9]",)))
+  assert.eq(g.marked-squares, ((), (), ()))
+}
+
+// Test PGN clocks and evaluations.
+#{
+  let g = bnp.pgn("[TimeControl \"180+2\"]\n\n1. e4 {[%clk 0:03:00] [%eval 0.3]} e5 {[%clk 0:02:59.5]} 2. Nf3 {[%emt 0:00:07] Nice} Nc6 {[%clk 0:02:51.2] [%eval #-3]} *")
+  assert.eq(g.clocks, (none, 180, 179.5, none, 171.2))
+  assert.eq(g.elapsed-times, (none, none, none, 7, none))
+  assert.eq(g.evaluations, (none, 0.3, none, none, none))
+  assert.eq(g.mates, (none, none, none, none, -3))
+  assert.eq(g.time-spent, (2, 2.5, 7, 10.3))
+  assert.eq(g.comments.at(3), ("Nice",))
+  assert.eq(g.parsed-tags.TimeControl, ((moves: none, seconds: 180, increment: 2),))
+}
+#{
+  let g = bnp.pgn("1. e4 {good [%clk abc] move} e5 {see [%eval] here} 2. Nf3 {[%emt 1:2:3:4] [%eval 0.5]} *")
+  assert.eq(g.comments, ((), ("good [%clk abc] move",), ("see [%eval] here",), ("[%emt 1:2:3:4]",)))
+  assert.eq(g.clocks, (none, none, none, none))
+  assert.eq(g.elapsed-times, (none, none, none, none))
+  assert.eq(g.evaluations, (none, none, none, 0.5))
+}
+#assert.eq(
+  bnp.pgn("[TimeControl \"2/60:30+5\"]\n\n1. e4 {[%clk 0:00:58]} e5 {[%clk 0:00:59]} 2. d4 {[%clk 0:01:20]} d5 {[%clk 0:01:25]} 3. c4 {[%clk 0:01:20]} *").time-spent,
+  (2, 1, 8, 4, 5),
+)
+
+// Test numeric annotation glyphs.
+#{
+  let g = bnp.pgn("1. e4!? $14 e5 2. Qh5+ $2 $9 Nc6?! *")
+  assert.eq(g.nags.map(nags => nags.map(nag => nag.code)), ((5, 14), (), (2, 9), (6,)))
+  assert.eq(g.nags.first().map(nag => nag.symbol), ("!?", "⩲"))
+  assert.eq(g.nags.at(2).last().meaning, "worst move")
+  assert.eq(g.check-marks, (none, none, "+", none))
+}
+#assert.eq(bnp.play("e4 e5 Qh5 Nc6 Bc4 Nf6 Qxf7#!!").nags.last().first().code, 3)
+#assert.eq(bnp.pgn("1. e4 $200 *").nags.first().first().symbol, none)
+
+// Test PGN tags.
+#{
+  let g = bnp.pgn("[Event \"Test\"]\n[Date \"1992.11.??\"]\n[Round \"3.1\"]\n[White \"A\"]\n[WhiteElo \"2750\"]\n[BlackElo \"-\"]\n\n1. e4 *")
+  assert.eq(g.tags.keys(), ("Event", "Date", "Round", "White", "WhiteElo", "BlackElo"))
+  assert.eq(g.tags.White, "A")
+  assert.eq(g.parsed-tags, (
+    Date: (year: 1992, month: 11, day: none),
+    Round: (3, 1),
+    WhiteElo: 2750,
+    BlackElo: none,
+  ))
+}
+#assert.eq(bnp.pgn("[Date \"1992.13.01\"]\n\n*").parsed-tags.Date, none)
+
+// Test PGN files containing multiple games.
+#{
+  let db = "[White \"A\"]\n[Black \"B\"]\n\n1. e4 e5 1-0\n\n[White \"C\"]\n[Black \"A\"]\n\n1. e4 Qxx *\n\n[White \"B\"]\n[Black \"C\"]\n\n1. d4 d5 1/2-1/2\n"
+  let games = bnp.pgn-games(db)
+  assert.eq(games.map(g => g.index), (0, 1, 2))
+  assert.eq(games.map(g => g.error == none), (true, false, true))
+  assert.eq(games.at(1).tags, (White: "C", Black: "A"))
+  assert.eq(games.at(2).san, ("d4", "d5"))
+  assert.eq(bnp.pgn-games(db, player: "A").map(g => g.index), (0, 1))
+  assert.eq(bnp.pgn-games(db, index: 2).map(g => g.index), (2,))
+  assert.eq(bnp.pgn-games(db, tags: (White: ("A", "B"))).map(g => g.index), (0, 2))
+  assert.eq(bnp.pgn-games(db, index: ()), ())
+}
+
+// Test strict PGN validation.
+#assert("warnings" not in bnp.pgn("1. e4 *"))
+#{
+  let warnings = bnp.pgn("[White \"A\"]\n[Result \"1-0\"]\n\n1. f3 e5 2. g4+ Qh4+ 0-1", strict: true).warnings
+  assert.eq(warnings.filter(w => w.ply == none).len(), 6)
+  assert.eq(warnings.filter(w => w.ply != none).map(w => w.ply), (2, 3))
+  assert.eq(warnings.last().message, "move Qh4+ is marked as check, but gives checkmate")
+  let warnings = bnp.pgn("1. f3 e5 2. g4 Qh4# *", strict: true).warnings
+  assert.eq(warnings.last().ply, 4)
+  let warnings = bnp.pgn("1. e4 d5 2. d5 Qd5 *", strict: true).warnings
+  assert.eq(warnings.last().message, "move Qd5 captures a piece, but is not marked with x")
+}
+
+// Test PGN export.
+#assert.eq(
+  bnp.export-pgn("f3 e5 g4 Qh4#", tags: (White: "A", ECO: "A00")),
+  "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n[White \"A\"]\n[Black \"?\"]\n[Result \"0-1\"]\n[ECO \"A00\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n",
+)
+#assert(bnp.export-pgn("Kd7", starting-position: bnp.fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 12")).ends-with("[SetUp \"1\"]\n\n12... Kd7 *\n"))
+#assert(
+  bnp.export-pgn(
+    "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6 Be3 e5 Nb3 Be6 f3 Be7 Qd2 O-O O-O-O Nbd7 g4 b5 g5 b4 Ne2 Ne8 f4 a5 f5 a4 Nbd4 exd4 Nxd4 b3 Kb1 bxc2+ Nxc2",
+    starting-position: bnp.fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 5"),
+  ).ends-with("\n\n5. e4 c5 6. Nf3 d6 7. d4 cxd4 8. Nxd4 Nf6 9. Nc3 a6 10. Be3 e5 11. Nb3 Be6\n12. f3 Be7 13. Qd2 O-O 14. O-O-O Nbd7 15. g4 b5 16. g5 b4 17. Ne2 Ne8 18. f4 a5\n19. f5 a4 20. Nbd4 exd4 21. Nxd4 b3 22. Kb1 bxc2+ 23. Nxc2 *\n")
+)
+#{
+  let original = "[White \"A\"]\n\n1. e4 {Good} e5! (1... c5 $14) 2. Nf3 *"
+  let normalized = bnp.normalize-pgn(original)
+  assert(normalized.ends-with("1. e4 {Good} 1... e5 $1 (1... c5 $14) 2. Nf3 *\n"))
+  assert.eq(bnp.pgn(normalized).san, bnp.pgn(original).san)
+  assert.eq(bnp.pgn(normalized).variations, bnp.pgn(original).variations)
+  assert.eq(bnp.normalize-pgn(normalized), normalized)
+}
+#{
+  let original = "1. e4 ; comment with } brace\ne5 (1... c5 ; sideline }\n) 2. Nf3 *"
+  let normalized = bnp.normalize-pgn(original)
+  assert(normalized.ends-with("\n\n1. e4 ;comment with } brace\n1... e5 (1... c5 ;sideline }\n) 2. Nf3 *\n"))
+  assert.eq(bnp.pgn(normalized).comments, bnp.pgn(original).comments)
+  assert.eq(bnp.normalize-pgn(normalized), normalized)
+}
+
 // Test inverting positions.
 #assert.eq(bnp.invert-position(bnp.starting-position).fen, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1")
 #assert.eq(bnp.invert-position(bnp.invert-position(bnp.starting-position)).fen, bnp.starting-position.fen)
 #assert.eq(bnp.invert-position(bnp.fen("rQ6/P7/8/q7/1R6/8/8/k3K3 w - - 0 1")).fen, "K3k3/8/8/1r6/Q7/8/p7/Rq6 b - - 0 1")
+#assert.eq(bnp.invert-position(bnp.fen("8/8/8/3p4/4P3/8/8/8 w - - 0 1")).fen, "8/8/8/4p3/3P4/8/8/8 b - - 0 1")
+
+// Test canonicalizing positions.
+#assert.eq(bnp.canonicalize-position(bnp.starting-position).position.fen, bnp.starting-position.fen)
+#assert.eq(bnp.canonicalize-position(bnp.starting-position).changes, ())
+#assert.eq(bnp.canonicalize-position(bnp.fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1")).position.fen, "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")
+#assert.eq(bnp.canonicalize-position(bnp.fen("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1")).changes, ())
+#assert.eq(bnp.canonicalize-position(bnp.fen("r3k2r/8/8/8/8/8/8/4K3 w KQkq - 12 40"), reset-move-counters: true), (
+  position: bnp.fen("r3k2r/8/8/8/8/8/8/4K3 w kq - 0 1"),
+  changes: (
+    "removed castling availability K (the rook is not on h1)",
+    "removed castling availability Q (the rook is not on a1)",
+    "reset halfmove clock from 12 to 0",
+    "reset fullmove number from 40 to 1",
+  ),
+))
+
+// Test X-FEN and Shredder-FEN castling availabilities.
+#assert.eq(bnp.shredder-fen(bnp.starting-position), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1")
+#assert.eq(bnp.shredder-fen(bnp.fen("bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w KQkq - 0 1")), "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w GEge - 0 1")
+#assert.eq(bnp.invert-position(bnp.fen("bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w GEe - 0 1")).fen, "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN b Qkq - 0 1")
+#assert.eq(bnp.canonicalize-position(bnp.fen("4k3/8/8/8/8/8/8/4KBRR w G - 0 1")).position.fen, "4k3/8/8/8/8/8/8/4KBRR w G - 0 1")
+
+// Test legal moves.
+#assert.eq(bnp.legal-moves(bnp.starting-position).len(), 20)
+#assert.eq(bnp.legal-moves(bnp.starting-position, square: "g1").map(m => m.san), ("Nf3", "Nh3"))
+#assert.eq(bnp.legal-moves(bnp.fen("k7/8/8/1N3N2/8/1N6/8/4K3 w - - 0 1"), square: "b5").last().san, "Nb5d4")
+#assert.eq(bnp.legal-moves(bnp.fen("R5k1/5ppp/8/8/8/8/8/4K3 b - - 0 1")), ())
+
+// Test position hashes and identity.
+#assert.eq(bnp.position-hash(bnp.starting-position).len(), 16)
+#assert.eq(bnp.position-hash(bnp.play("e4 e5 Nf3 Nc6").positions.last()), bnp.position-hash(bnp.play("Nf3 Nc6 e4 e5").positions.last()))
+#assert.ne(bnp.position-hash(bnp.starting-position), bnp.position-hash(bnp.fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1")))
+#assert(bnp.same-position(bnp.starting-position, bnp.play("Nf3 Nf6 Ng1 Ng8").positions.last()))
+#assert(bnp.same-position(bnp.fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"), bnp.fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")))
+#assert(not bnp.same-position(bnp.fen("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1"), bnp.fen("4k3/8/8/8/3pP3/8/8/4K3 b - - 0 1")))
+#assert(not bnp.same-position(bnp.starting-position, bnp.fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w Qkq - 0 1")))
+
+// Test attack maps.
+#assert.eq(bnp.attack-map(bnp.starting-position).len(), 64)
+#assert.eq(bnp.attack-map(bnp.starting-position).f3, (white: ("g1", "e2", "g2"), black: ()))
+#assert.eq(bnp.attack-map(bnp.starting-position).e4, (white: (), black: ()))
+#assert.eq(bnp.attack-map(bnp.fen("4k3/8/8/8/8/8/3Q4/3RK3 w - - 0 1")).d5, (white: ("d2", ), black: ()))
+#assert.eq(bnp.attack-map(bnp.fen("4k3/8/8/8/8/8/3Q4/3RK3 w - - 0 1"), x-ray: true).d5, (white: ("d1", "d2"), black: ()))
+#assert.eq(bnp.attack-map(bnp.fen("4k3/8/8/8/8/8/3Q4/3RK3 w - - 0 1"), x-ray: true).c3, (white: ("d2", ), black: ()))
+
+// Test perft.
+#assert.eq(bnp.perft(bnp.starting-position, 0), 1)
+#assert.eq(bnp.perft(bnp.starting-position, 3), 8902)
+#assert.eq(bnp.perft(bnp.fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"), 2), 2039)
+#assert.eq(bnp.perft(bnp.starting-position, 2, divide: true).nodes, 400)
+#assert.eq(bnp.perft(bnp.starting-position, 2, divide: true).moves.find(m => m.san == "Nf3"), (from: "g1", to: "f3", san: "Nf3", nodes: 20))